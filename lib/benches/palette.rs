@@ -0,0 +1,50 @@
+//! Throughput of RGB to palette conversion, linear scan vs lookup table.
+//!
+//! Run with `cargo bench --bench palette`.
+
+extern crate launchpad;
+
+use std::time::{Duration, Instant};
+use launchpad::*;
+
+/// One 9x9 frame worth of LEDs
+const FRAME_LEDS: usize = 81;
+const FRAMES: usize = 2000;
+
+fn frame_color(frame: usize, led: usize) -> (u8, u8, u8) {
+    let n = frame * FRAME_LEDS + led;
+    ((n * 7) as u8, (n * 13) as u8, (n * 29) as u8)
+}
+
+fn run<F: Fn(u8, u8, u8) -> u8>(name: &str, convert: F) {
+    let start = Instant::now();
+    let mut checksum = 0u32;
+    for frame in 0..FRAMES {
+        for led in 0..FRAME_LEDS {
+            let (r, g, b) = frame_color(frame, led);
+            checksum = checksum.wrapping_add(convert(r, g, b) as u32);
+        }
+    }
+    let elapsed = start.elapsed();
+    let per_frame = elapsed / FRAMES as u32;
+    let budget = Duration::from_millis(16);
+
+    println!("{:<24} {:>10.2?} per frame, {:>12.0} colors/s, {:.4}% of a 16ms frame (checksum {})",
+             name,
+             per_frame,
+             (FRAMES * FRAME_LEDS) as f64 / elapsed.as_secs_f64(),
+             100.0 * per_frame.as_secs_f64() / budget.as_secs_f64(),
+             checksum);
+}
+
+fn main() {
+    let start = Instant::now();
+    let lookup = PaletteLookup::new(LOOKUP_BITS);
+    println!("Built {} bit lookup table in {:.2?}", LOOKUP_BITS, start.elapsed());
+
+    run("nearest_palette", nearest_palette);
+    run("PaletteLookup::nearest", |r, g, b| lookup.nearest(r, g, b));
+    // Build the shared table up front so it isn't counted against the frames
+    nearest_palette_fast(0, 0, 0);
+    run("nearest_palette_fast", nearest_palette_fast);
+}
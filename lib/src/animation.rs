@@ -0,0 +1,364 @@
+//! Keyframe animation of the LEDs.
+//!
+//! A `Timeline` holds tracks of keyframes, each lighting a region of the
+//! device. Between keyframes colors are interpolated in RGB along an easing
+//! curve. A `Player` samples the timeline at a fixed frame rate and sends the
+//! LEDs which changed to the device.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use color::{LedColor, RGBColor};
+use frame::{Frame, Location, GRID_SIZE};
+use launchpad::LaunchpadMk2;
+
+/// How a color moves from one keyframe to the next
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Easing {
+    /// Hold the previous color, then jump at the keyframe
+    Step,
+    Linear,
+    /// Start slowly, then speed up
+    EaseIn,
+    /// Start quickly, then slow down
+    EaseOut,
+    /// Slow at both ends
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map progress through a segment (0...1) to progress between its colors
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Step => if t < 1.0 { 0.0 } else { 1.0 },
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// LEDs lit by a track
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Region {
+    /// A single pad or button
+    Led(Location),
+    /// A row of pads, 0 being the top
+    Row(usize),
+    /// A column of pads, 0 being the left
+    Column(usize),
+    /// A rectangle of pads with its top left corner at `(x, y)`
+    Rect {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    /// Every pad, but not the buttons
+    AllPads,
+}
+
+impl Region {
+    /// Set every LED in the region to a color
+    pub fn fill(&self, frame: &mut Frame, color: LedColor) {
+        let (x, y, width, height) = match *self {
+            Region::Led(location) => {
+                frame.set(location, color);
+                return;
+            }
+            Region::Row(y) => (0, y, GRID_SIZE, 1),
+            Region::Column(x) => (x, 0, 1, GRID_SIZE),
+            Region::Rect { x, y, width, height } => (x, y, width, height),
+            Region::AllPads => (0, 0, GRID_SIZE, GRID_SIZE),
+        };
+        for py in y..(y + height).min(GRID_SIZE) {
+            for px in x..(x + width).min(GRID_SIZE) {
+                frame.set_pad(px, py, color);
+            }
+        }
+    }
+}
+
+/// The color of a track at a point in time
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Keyframe {
+    #[cfg_attr(feature = "serde", serde(with = "::serialize::millis"))]
+    pub time: Duration,
+    pub color: LedColor,
+    /// How the color moves from the previous keyframe to this one
+    pub easing: Easing,
+}
+
+/// Keyframes for one region, in time order
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Track {
+    pub region: Region,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Track {
+    pub fn new(region: Region) -> Track {
+        Track {
+            region,
+            keyframes: Vec::new(),
+        }
+    }
+
+    /// Add a keyframe, reached from the previous one along `easing`
+    pub fn key<C: Into<LedColor>>(mut self, time: Duration, color: C, easing: Easing) -> Track {
+        let key = Keyframe {
+            time,
+            color: color.into(),
+            easing,
+        };
+        let index = self.keyframes.iter().position(|k| k.time > time).unwrap_or(self.keyframes.len());
+        self.keyframes.insert(index, key);
+        self
+    }
+
+    /// The color at a point in time. Before the first keyframe and after the
+    /// last one the track holds their colors. `None` for an empty track.
+    pub fn sample(&self, time: Duration) -> Option<LedColor> {
+        let next = self.keyframes.iter().position(|k| k.time > time);
+        match next {
+            None => self.keyframes.last().map(|k| k.color),
+            Some(0) => Some(self.keyframes[0].color),
+            Some(i) => {
+                let (from, to) = (&self.keyframes[i - 1], &self.keyframes[i]);
+                let span = (to.time - from.time).as_secs_f32();
+                let t = (time - from.time).as_secs_f32() / span;
+                Some(interpolate(from.color, to.color, to.easing.apply(t)))
+            }
+        }
+    }
+}
+
+/// Blend two colors, `t` being 0 for `from` and 1 for `to`
+fn interpolate(from: LedColor, to: LedColor, t: f32) -> LedColor {
+    if t <= 0.0 || from == to {
+        return from;
+    }
+    if t >= 1.0 {
+        return to;
+    }
+    let (a, b) = (from.rgb(), to.rgb());
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    LedColor::Rgb(RGBColor::new(mix(a.red(), b.red()),
+                                mix(a.green(), b.green()),
+                                mix(a.blue(), b.blue())))
+}
+
+/// A light show: tracks drawn in order, so later tracks cover earlier ones
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Timeline {
+    pub tracks: Vec<Track>,
+}
+
+impl Timeline {
+    pub fn new() -> Timeline {
+        Timeline::default()
+    }
+
+    /// Add a track on top of the existing ones
+    pub fn track(mut self, track: Track) -> Timeline {
+        self.tracks.push(track);
+        self
+    }
+
+    /// Time of the last keyframe
+    pub fn duration(&self) -> Duration {
+        self.tracks
+            .iter()
+            .filter_map(|t| t.keyframes.last())
+            .map(|k| k.time)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Draw every track at a point in time onto a frame. LEDs no track
+    /// covers are left alone.
+    pub fn draw(&self, frame: &mut Frame, time: Duration) {
+        for track in &self.tracks {
+            if let Some(color) = track.sample(time) {
+                track.region.fill(frame, color);
+            }
+        }
+    }
+
+    /// The frame at a point in time, with LEDs no track covers off
+    pub fn sample(&self, time: Duration) -> Frame {
+        let mut frame = Frame::new();
+        self.draw(&mut frame, time);
+        frame
+    }
+}
+
+/// Plays a `Timeline` onto a device at a fixed frame rate
+#[derive(Debug)]
+pub struct Player {
+    timeline: Timeline,
+    frame_interval: Duration,
+    position: Duration,
+    looping: bool,
+    paused: bool,
+    last_update: Option<Instant>,
+    next_frame: Option<Instant>,
+    shown: Option<Frame>,
+}
+
+impl Player {
+    /// Play a timeline from the start at `fps` frames per second
+    pub fn new(timeline: Timeline, fps: u32) -> Player {
+        assert!(fps > 0);
+        Player {
+            timeline,
+            frame_interval: Duration::from_secs(1) / fps,
+            position: Duration::from_secs(0),
+            looping: false,
+            paused: false,
+            last_update: None,
+            next_frame: None,
+            shown: None,
+        }
+    }
+
+    /// Start over at the beginning when the end is reached
+    pub fn looping(mut self, looping: bool) -> Player {
+        self.looping = looping;
+        self
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Jump to a point in the timeline
+    pub fn seek(&mut self, position: Duration) {
+        self.position = position.min(self.timeline.duration());
+        self.next_frame = None;
+    }
+
+    /// The current point in the timeline
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    /// Whether a non-looping timeline has played to its end
+    pub fn finished(&self) -> bool {
+        !self.looping && self.position >= self.timeline.duration()
+    }
+
+    /// Move the timeline along to `now`, without drawing anything
+    fn advance(&mut self, now: Instant) {
+        let elapsed = self.last_update.map_or(Duration::from_secs(0), |last| now - last);
+        self.last_update = Some(now);
+        if self.paused {
+            return;
+        }
+
+        let duration = self.timeline.duration();
+        self.position += elapsed;
+        if self.position >= duration {
+            self.position = if self.looping && duration > Duration::from_secs(0) {
+                Duration::from_nanos((self.position.as_nanos() % duration.as_nanos()) as u64)
+            } else {
+                duration
+            };
+        }
+    }
+
+    /// Advance the timeline and, if a frame is due, draw the LEDs which
+    /// changed. Call this often from the application's loop. Returns whether
+    /// a frame was drawn.
+    pub fn update(&mut self, lpad: &mut LaunchpadMk2) -> bool {
+        let now = Instant::now();
+        self.advance(now);
+        if self.next_frame.is_some_and(|next| now < next) {
+            return false;
+        }
+        // Keep a steady rate, but don't try to catch up on missed frames
+        let next = self.next_frame.unwrap_or(now) + self.frame_interval;
+        self.next_frame = Some(if next < now { now + self.frame_interval } else { next });
+
+        let frame = self.timeline.sample(self.position);
+        match self.shown {
+            Some(ref shown) => lpad.draw_frame_diff(&frame, shown),
+            None => lpad.draw_frame(&frame),
+        }
+        self.shown = Some(frame);
+        true
+    }
+
+    /// Play until the timeline finishes. Never returns for a looping one,
+    /// unless it is paused: a paused player shows where it is and returns at
+    /// once, as nothing could resume it.
+    pub fn play(&mut self, lpad: &mut LaunchpadMk2) {
+        loop {
+            self.update(lpad);
+            if self.finished() || self.paused {
+                return;
+            }
+            thread::sleep(self.next_frame.map_or(self.frame_interval, |next| {
+                next.saturating_duration_since(Instant::now())
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emulator::Emulator;
+
+    #[test]
+    fn track_interpolates_between_keyframes() {
+        let black = RGBColor::new(0, 0, 0);
+        let white = RGBColor::new(200, 100, 0);
+        let track = Track::new(Region::AllPads)
+            .key(Duration::from_secs(1), black, Easing::Step)
+            .key(Duration::from_secs(3), white, Easing::Linear);
+
+        assert_eq!(track.sample(Duration::from_secs(0)), Some(LedColor::Rgb(black)));
+        assert_eq!(track.sample(Duration::from_secs(2)), Some(LedColor::Rgb(RGBColor::new(100, 50, 0))));
+        assert_eq!(track.sample(Duration::from_secs(9)), Some(LedColor::Rgb(white)));
+    }
+
+    #[test]
+    fn play_returns_when_paused() {
+        let timeline = || {
+            Timeline::new().track(Track::new(Region::AllPads).key(Duration::from_millis(20),
+                                                                  RGBColor::new(9, 9, 9),
+                                                                  Easing::Linear))
+        };
+        let (_emulator, mut lpad) = Emulator::new();
+        let mut player = Player::new(timeline(), 100);
+        player.seek(Duration::from_secs(1));
+        player.pause();
+        player.play(&mut lpad);
+        assert!(player.finished());
+        assert!(!player.looping(true).finished());
+
+        let mut player = Player::new(timeline(), 100).looping(true);
+        player.seek(Duration::from_millis(10));
+        player.pause();
+        player.play(&mut lpad);
+        assert_eq!(player.position(), Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,160 @@
+//! Calibration of RGB colors for the Launchpad's LEDs.
+//!
+//! The LEDs do not respond linearly, so sending 8 bit sRGB values straight to
+//! the 6 bit RGB sysex channels washes out mid-tones. A `Calibration` applies
+//! per-channel gamma, white balance and a global brightness before scaling.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use color::RGBColor;
+
+/// Largest value of a channel in the RGB sysex messages
+pub const RGB_CHANNEL_MAX: u8 = 63;
+
+/// Conversion from `RGBColor` to device channel values
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Calibration {
+    /// Exponent applied to each of red, green and blue (normalized to 0...1)
+    pub gamma: [f32; 3],
+    /// Multiplier for each of red, green and blue, applied after gamma
+    pub white_balance: [f32; 3],
+    /// Multiplier for all channels, applied after white balance
+    pub brightness: f32,
+}
+
+impl Default for Calibration {
+    /// A gamma of 2.2 on all channels, which suits most Mark 2 devices
+    fn default() -> Calibration {
+        Calibration {
+            gamma: [2.2; 3],
+            white_balance: [1.0; 3],
+            brightness: 1.0,
+        }
+    }
+}
+
+impl Calibration {
+    /// A calibration that only rescales 0...255 to 0...63
+    pub fn linear() -> Calibration {
+        Calibration {
+            gamma: [1.0; 3],
+            white_balance: [1.0; 3],
+            brightness: 1.0,
+        }
+    }
+
+    /// Convert a color to the red, green and blue values (0...63) to send
+    pub fn apply(&self, color: &RGBColor) -> [u8; 3] {
+        let channels = [color.red(), color.green(), color.blue()];
+        let mut out = [0u8; 3];
+        for (i, value) in channels.iter().enumerate() {
+            let linear = (*value as f32 / 255.0).powf(self.gamma[i]);
+            let scaled = linear * self.white_balance[i] * self.brightness;
+            out[i] = (scaled * RGB_CHANNEL_MAX as f32).round().clamp(0.0, RGB_CHANNEL_MAX as f32) as u8;
+        }
+        out
+    }
+
+    /// Parse a calibration profile. Profiles are lines of `key = value`, where
+    /// the keys are `gamma`, `white_balance` and `brightness`. `gamma` and
+    /// `white_balance` take either one value for all channels or three values
+    /// for red, green and blue. Missing keys keep their default, and `#`
+    /// starts a comment.
+    ///
+    /// ```text
+    /// gamma = 2.2 2.0 2.4
+    /// white_balance = 1.0 0.85 0.9
+    /// brightness = 0.8
+    /// ```
+    pub fn parse(profile: &str) -> Result<Calibration, CalibrationError> {
+        let mut cal = Calibration::default();
+
+        for (i, line) in profile.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = |message: &str| CalibrationError::Parse {
+                line: i + 1,
+                message: message.to_string(),
+            };
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts.next().ok_or_else(|| bad("expected `key = value`"))?;
+            let values = value.split_whitespace()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|_| bad("expected a number"))?;
+            if values.iter().any(|v| *v < 0.0) {
+                return Err(bad("values must not be negative"));
+            }
+
+            let channels = |values: &[f32]| match values.len() {
+                1 => Ok([values[0]; 3]),
+                3 => Ok([values[0], values[1], values[2]]),
+                _ => Err(bad("expected one or three values")),
+            };
+            match key {
+                "gamma" => cal.gamma = channels(&values)?,
+                "white_balance" => cal.white_balance = channels(&values)?,
+                "brightness" if values.len() == 1 => cal.brightness = values[0],
+                "brightness" => return Err(bad("expected one value")),
+                _ => return Err(bad("unknown key")),
+            }
+        }
+
+        Ok(cal)
+    }
+
+    /// Load a calibration profile from a file. See `parse` for the format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Calibration, CalibrationError> {
+        let profile = fs::read_to_string(path).map_err(CalibrationError::Io)?;
+        Calibration::parse(&profile)
+    }
+}
+
+/// Failure to load a calibration profile
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalibrationError::Io(ref err) => write!(f, "{}", err),
+            CalibrationError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for CalibrationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_profile() {
+        let cal = Calibration::parse("# bench pad\ngamma = 2.0 2.2 2.4\nbrightness = 0.5 # dimmer\n").unwrap();
+        assert_eq!(cal.gamma, [2.0, 2.2, 2.4]);
+        assert_eq!(cal.white_balance, [1.0; 3]);
+        assert_eq!(cal.brightness, 0.5);
+
+        assert!(Calibration::parse("gamma = 1 2").is_err());
+        assert!(Calibration::parse("contrast = 1").is_err());
+    }
+
+    #[test]
+    fn linear_scales_to_six_bits() {
+        let cal = Calibration::linear();
+        assert_eq!(cal.apply(&RGBColor::new(255, 0, 128)), [63, 0, 32]);
+    }
+}
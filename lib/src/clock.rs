@@ -0,0 +1,190 @@
+//! MIDI clock for the device's flashing and pulsing.
+//!
+//! A Launchpad Mark 2 times `flash_single` and `pulse_single` from the MIDI
+//! clock it receives, and blinks at 120 BPM when it receives none. A `Clock`
+//! sends that clock from a background thread, either generated at a set
+//! tempo or forwarded from another MIDI input, until it is stopped or
+//! dropped. It may outlive the `LaunchpadMk2` it was started for: the
+//! output port, and the PortMidi context if the device opened it, are kept
+//! until the clock stops.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use pm;
+use launchpad::LaunchpadMk2;
+
+/// MIDI clock ticks per beat
+pub const TICKS_PER_BEAT: u32 = 24;
+
+/// Slowest tempo the device follows, in beats per minute
+pub const MIN_BPM: f64 = 40.0;
+/// Fastest tempo the device follows, in beats per minute
+pub const MAX_BPM: f64 = 240.0;
+
+const TICK: [u8; 3] = [0xF8, 0, 0];
+
+/// A background MIDI clock sender. See the module documentation.
+pub struct Clock {
+    bpm: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    /// Kept until the thread, and the output port it holds, are gone
+    _midi: Option<Arc<pm::PortMidi>>,
+}
+
+impl Clock {
+    /// Start sending clock to the device at `bpm` beats per minute, clamped
+    /// to what the device follows.
+    pub fn start(lpad: &LaunchpadMk2, bpm: f64) -> Clock {
+        let output = lpad.shared_output();
+        Clock::spawn(lpad, bpm, move |bpm, stop| {
+            let mut next = Instant::now();
+            while !stop.load(Ordering::Relaxed) {
+                output.lock().expect("Output lock poisoned").write_message(TICK).expect("Fail");
+                // Schedule from the previous tick rather than from now, so
+                // time spent writing doesn't make the clock drift
+                next += tick_interval(load_bpm(&bpm));
+                let now = Instant::now();
+                if next > now {
+                    thread::sleep(next - now);
+                }
+            }
+        })
+    }
+
+    /// Forward the clock arriving on `input`, e.g. from a DAW or drum
+    /// machine, to the device. Other messages on `input` are ignored. The
+    /// tempo is measured from the ticks as they arrive. The PortMidi context
+    /// `input` was opened from must outlive the clock.
+    pub fn forward(lpad: &LaunchpadMk2, input: pm::InputPort) -> Clock {
+        let output = lpad.shared_output();
+        Clock::spawn(lpad, 120.0, move |bpm, stop| {
+            // Measure over whole beats, as ticks are only read every
+            // millisecond or so
+            let mut beat: Option<(Instant, u32)> = None;
+            while !stop.load(Ordering::Relaxed) {
+                let events = input.read_n(1024).expect("Failed to read").unwrap_or_default();
+                for _ in events.iter().filter(|e| e.message.status == 0xF8) {
+                    output.lock().expect("Output lock poisoned").write_message(TICK).expect("Fail");
+
+                    let now = Instant::now();
+                    beat = match beat {
+                        Some((start, ticks)) if ticks + 1 == TICKS_PER_BEAT => {
+                            store_bpm(&bpm, 60.0 / (now - start).as_secs_f64());
+                            Some((now, 0))
+                        }
+                        Some((start, ticks)) => Some((start, ticks + 1)),
+                        None => Some((now, 0)),
+                    };
+                }
+                // there is no blocking receive method in PortMidi
+                thread::sleep(Duration::from_millis(1));
+            }
+        })
+    }
+
+    fn spawn<F>(lpad: &LaunchpadMk2, bpm: f64, run: F) -> Clock
+        where F: FnOnce(Arc<AtomicU64>, Arc<AtomicBool>) + Send + 'static
+    {
+        let bpm = Arc::new(AtomicU64::new(clamp_bpm(bpm).to_bits()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (bpm, stop) = (bpm.clone(), stop.clone());
+            thread::spawn(move || run(bpm, stop))
+        };
+        Clock {
+            bpm,
+            stop,
+            thread: Some(thread),
+            _midi: lpad.shared_midi(),
+        }
+    }
+
+    /// The tempo, in beats per minute. For a forwarded clock this is the
+    /// tempo of the last whole beat received.
+    pub fn bpm(&self) -> f64 {
+        load_bpm(&self.bpm)
+    }
+
+    /// Change the tempo of a clock made by `start`, clamped to what the
+    /// device follows. The next tick is sent at the new tempo. A forwarded
+    /// clock follows its source instead.
+    pub fn set_bpm(&self, bpm: f64) {
+        store_bpm(&self.bpm, clamp_bpm(bpm));
+    }
+
+    /// Stop sending clock. The device goes back to blinking at 120 BPM.
+    pub fn stop(mut self) {
+        self.halt();
+    }
+
+    fn halt(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("Clock thread panicked");
+        }
+    }
+}
+
+impl Drop for Clock {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
+
+/// Time between clock ticks at a tempo
+pub fn tick_interval(bpm: f64) -> Duration {
+    Duration::from_secs_f64(60.0 / (clamp_bpm(bpm) * TICKS_PER_BEAT as f64))
+}
+
+fn clamp_bpm(bpm: f64) -> f64 {
+    assert!(!bpm.is_nan(), "Bad BPM!");
+    bpm.clamp(MIN_BPM, MAX_BPM)
+}
+
+fn load_bpm(bpm: &AtomicU64) -> f64 {
+    f64::from_bits(bpm.load(Ordering::Relaxed))
+}
+
+fn store_bpm(bpm: &AtomicU64, value: f64) {
+    bpm.store(value.to_bits(), Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emulator::Emulator;
+    use session::Recorder;
+
+    #[test]
+    fn tick_interval_is_24_per_beat() {
+        assert_eq!(tick_interval(120.0), Duration::from_secs_f64(0.5 / 24.0));
+        assert_eq!(tick_interval(1000.0), tick_interval(MAX_BPM));
+        assert_eq!(tick_interval(0.0), tick_interval(MIN_BPM));
+    }
+
+    #[test]
+    fn start_and_stop() {
+        let (_emulator, mut lpad) = Emulator::new();
+        let recorder = Recorder::attach(&mut lpad);
+        let ticks = || recorder.session().messages.iter().filter(|m| m.bytes == TICK).count();
+
+        let clock = Clock::start(&lpad, MAX_BPM);
+        assert_eq!(clock.bpm(), MAX_BPM);
+        clock.set_bpm(1.0);
+        assert_eq!(clock.bpm(), MIN_BPM);
+        clock.set_bpm(MAX_BPM);
+        // The clock keeps running without the device
+        drop(lpad);
+        thread::sleep(Duration::from_millis(100));
+        clock.stop();
+
+        let sent = ticks();
+        assert!(sent >= 2, "{} ticks", sent);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(ticks(), sent);
+    }
+}
@@ -1,10 +1,34 @@
-#[derive(Debug, Copy, Clone)]
+use std::sync::OnceLock;
+
+use launchpad::Color;
+use palette;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct RGBColor {
     red: u8,
     green: u8,
     blue: u8,
 }
 
+impl RGBColor {
+    /// Create a color from its 8 bit channels
+    pub const fn new(red: u8, green: u8, blue: u8) -> RGBColor {
+        RGBColor { red, green, blue }
+    }
+
+    pub fn red(&self) -> u8 {
+        self.red
+    }
+
+    pub fn green(&self) -> u8 {
+        self.green
+    }
+
+    pub fn blue(&self) -> u8 {
+        self.blue
+    }
+}
+
 /// Use the 3d nearest neighbor to determine an approximation for RGB colors
 pub fn nearest_palette(red: u8, green: u8, blue: u8) -> u8 {
     let mut ifurthest = 0usize;
@@ -27,6 +51,173 @@ pub fn nearest_palette(red: u8, green: u8, blue: u8) -> u8 {
     return ifurthest as u8;
 }
 
+/// The palette color exactly matching an RGB color, if there is one
+pub fn exact_palette(color: &RGBColor) -> Option<Color> {
+    COLOR_PALETTE.iter().position(|c| c == color).map(|i| i as Color)
+}
+
+/// A color written as a palette name such as `RED_DIM`, in any case, or as
+/// `#rrggbb`
+pub fn parse_color(word: &str) -> Option<LedColor> {
+    if word.starts_with('#') && word.len() == 7 {
+        let channel = |i: usize| u8::from_str_radix(word.get(i..i + 2)?, 16).ok();
+        return Some(LedColor::Rgb(RGBColor::new(channel(1)?, channel(3)?, channel(5)?)));
+    }
+    palette::by_name(word).map(LedColor::Palette)
+}
+
+/// The color of a single LED, either from the palette or any RGB value.
+/// Lighting an `Rgb` color which exactly matches a `COLOR_PALETTE` entry
+/// uses the shorter palette message. `Off`, `Palette(0)` and black `Rgb` all
+/// leave the LED dark, and compare equal.
+#[derive(Debug, Copy, Clone, Eq, Default)]
+pub enum LedColor {
+    #[default]
+    Off,
+    Palette(Color),
+    Rgb(RGBColor),
+}
+
+impl LedColor {
+    /// The palette color to send for this color, if it can be sent as one
+    pub fn palette(&self) -> Option<Color> {
+        match *self {
+            LedColor::Off => Some(0),
+            LedColor::Palette(color) => Some(color),
+            LedColor::Rgb(ref color) => exact_palette(color),
+        }
+    }
+
+    /// The palette color to send for this color, using the nearest palette
+    /// color for RGB colors which are not in the palette
+    pub fn nearest_palette(&self) -> Color {
+        match *self {
+            LedColor::Rgb(ref color) => nearest_palette(color.red, color.green, color.blue),
+            _ => self.palette().unwrap_or(0),
+        }
+    }
+
+    /// The RGB value of this color, looking palette colors up in
+    /// `COLOR_PALETTE`. Panics on a palette color over 127, like the lighting
+    /// calls.
+    pub fn rgb(&self) -> RGBColor {
+        match *self {
+            LedColor::Off => COLOR_PALETTE[0],
+            LedColor::Palette(color) => *COLOR_PALETTE.get(color as usize).expect("Bad Color!"),
+            LedColor::Rgb(color) => color,
+        }
+    }
+}
+
+impl LedColor {
+    /// `Off` for any color which leaves the LED dark
+    fn normalized(&self) -> LedColor {
+        match *self {
+            LedColor::Palette(0) => LedColor::Off,
+            LedColor::Rgb(RGBColor { red: 0, green: 0, blue: 0 }) => LedColor::Off,
+            color => color,
+        }
+    }
+}
+
+impl PartialEq for LedColor {
+    fn eq(&self, other: &LedColor) -> bool {
+        match (self.normalized(), other.normalized()) {
+            (LedColor::Off, LedColor::Off) => true,
+            (LedColor::Palette(a), LedColor::Palette(b)) => a == b,
+            (LedColor::Rgb(a), LedColor::Rgb(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl From<Color> for LedColor {
+    fn from(color: Color) -> LedColor {
+        LedColor::Palette(color)
+    }
+}
+
+impl From<RGBColor> for LedColor {
+    fn from(color: RGBColor) -> LedColor {
+        LedColor::Rgb(color)
+    }
+}
+
+/// Bits per channel kept by the table behind `nearest_palette_fast`
+pub const LOOKUP_BITS: u32 = 6;
+
+/// A precomputed table mapping quantized RGB values to their nearest palette
+/// color. Each channel is truncated to `bits` bits, so a 6 bit table holds
+/// 2^18 entries (256KiB) and turns `nearest_palette` into a single index.
+/// Palette colors themselves are always matched exactly.
+pub struct PaletteLookup {
+    bits: u32,
+    table: Vec<u8>,
+    /// Every palette color packed as `0xRRGGBB`, sorted, with its first index
+    exact: Vec<(u32, u8)>,
+}
+
+fn pack(red: u8, green: u8, blue: u8) -> u32 {
+    (red as u32) << 16 | (green as u32) << 8 | blue as u32
+}
+
+impl PaletteLookup {
+    /// Build a table keeping `bits` (1...8) bits of each channel. Every cell
+    /// stores the palette color nearest to the center of the RGB cube it covers.
+    pub fn new(bits: u32) -> PaletteLookup {
+        assert!((1..=8).contains(&bits), "Bad Lookup Bits!");
+        let levels = 1usize << bits;
+        let shift = 8 - bits;
+        let center = |q: usize| ((q << shift) | ((1 << shift) >> 1)) as u8;
+
+        let mut table = Vec::with_capacity(levels * levels * levels);
+        for r in 0..levels {
+            for g in 0..levels {
+                for b in 0..levels {
+                    table.push(nearest_palette(center(r), center(g), center(b)));
+                }
+            }
+        }
+
+        let mut exact: Vec<(u32, u8)> = COLOR_PALETTE.iter()
+            .enumerate()
+            .map(|(i, c)| (pack(c.red, c.green, c.blue), i as u8))
+            .collect();
+        // Stable, so duplicated colors keep their first index first
+        exact.sort_by_key(|&(color, _)| color);
+        exact.dedup_by_key(|&mut (color, _)| color);
+
+        PaletteLookup {
+            bits,
+            table,
+            exact,
+        }
+    }
+
+    /// Look up the palette color nearest to the given RGB value
+    pub fn nearest(&self, red: u8, green: u8, blue: u8) -> u8 {
+        // A cell answers for its center, which a palette color in it may not
+        // be nearest to
+        if let Ok(i) = self.exact.binary_search_by_key(&pack(red, green, blue), |&(color, _)| color) {
+            return self.exact[i].1;
+        }
+        let shift = 8 - self.bits;
+        let index = ((red as usize >> shift) << (2 * self.bits)) |
+                    ((green as usize >> shift) << self.bits) |
+                    (blue as usize >> shift);
+        self.table[index]
+    }
+}
+
+/// Like `nearest_palette`, but answered from a `PaletteLookup` of `LOOKUP_BITS`
+/// bits which is built on first use. Colors within the same quantization cell
+/// share an answer, so this may differ slightly from `nearest_palette`, but
+/// palette colors always map to themselves.
+pub fn nearest_palette_fast(red: u8, green: u8, blue: u8) -> u8 {
+    static LOOKUP: OnceLock<PaletteLookup> = OnceLock::new();
+    LOOKUP.get_or_init(|| PaletteLookup::new(LOOKUP_BITS)).nearest(red, green, blue)
+}
+
 
 /// palette Table information from http://launchpaddr.com/mk2palette/
 pub const COLOR_PALETTE: [RGBColor; 128] = [
@@ -162,3 +353,76 @@ pub const COLOR_PALETTE: [RGBColor; 128] = [
     RGBColor{red: 0xb4, green: 0x5d, blue: 0x00},
     RGBColor{red: 0x4c, green: 0x13, blue: 0x00},
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(color: &RGBColor, red: u8, green: u8, blue: u8) -> f64 {
+        let d = |a: u8, b: u8| (a as f64 - b as f64).powi(2);
+        (d(color.red, red) + d(color.green, green) + d(color.blue, blue)).sqrt()
+    }
+
+    #[test]
+    fn exact() {
+        assert_eq!(exact_palette(&COLOR_PALETTE[5]), Some(5));
+        // Duplicated colors find their first entry
+        assert_eq!(COLOR_PALETTE[25], COLOR_PALETTE[21]);
+        assert_eq!(exact_palette(&COLOR_PALETTE[25]), Some(21));
+        assert_eq!(exact_palette(&RGBColor::new(1, 2, 3)), None);
+
+        assert_eq!(LedColor::Rgb(COLOR_PALETTE[9]).palette(), Some(9));
+        assert_eq!(LedColor::Off.rgb(), COLOR_PALETTE[0]);
+    }
+
+    #[test]
+    fn dark_colors_are_equal() {
+        assert_eq!(LedColor::from(0), LedColor::Off);
+        assert_eq!(LedColor::Rgb(RGBColor::new(0, 0, 0)), LedColor::Palette(0));
+        assert_ne!(LedColor::Rgb(RGBColor::new(0, 0, 1)), LedColor::Off);
+        assert_ne!(LedColor::Palette(1), LedColor::Off);
+        assert_ne!(LedColor::Rgb(COLOR_PALETTE[5]), LedColor::Palette(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bad Color!")]
+    fn palette_out_of_range() {
+        LedColor::Palette(200).rgb();
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("red dim"), Some(LedColor::Palette(palette::RED_DIM)));
+        assert_eq!(parse_color("#0080FF"), Some(LedColor::Rgb(RGBColor::new(0, 128, 255))));
+        assert_eq!(parse_color("#0080F"), None);
+        assert_eq!(parse_color("#0080FG"), None);
+        assert_eq!(parse_color("MAUVE"), None);
+    }
+
+    #[test]
+    fn lookup_keeps_palette_colors() {
+        for color in COLOR_PALETTE.iter() {
+            let fast = nearest_palette_fast(color.red, color.green, color.blue);
+            let found = &COLOR_PALETTE[fast as usize];
+            assert_eq!((found.red, found.green, found.blue), (color.red, color.green, color.blue));
+        }
+    }
+
+    #[test]
+    fn lookup_error_is_bounded() {
+        // Within a cell, the answer is nearest to its center, at most half a
+        // cell diagonal away: 2 * sqrt(3 * 2^2) for a 6 bit table
+        for red in (0..=255).step_by(9) {
+            for green in (0..=255).step_by(9) {
+                for blue in (0..=255).step_by(9) {
+                    let (red, green, blue) = (red as u8, green as u8, blue as u8);
+                    let exact = nearest_palette(red, green, blue) as usize;
+                    let fast = nearest_palette_fast(red, green, blue) as usize;
+                    let error = distance(&COLOR_PALETTE[fast], red, green, blue) -
+                                distance(&COLOR_PALETTE[exact], red, green, blue);
+                    assert!(error <= 7.0, "{} {} {}: {}", red, green, blue, error);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Finding Launchpads among the MIDI devices PortMidi reports.
+
+use pm;
+
+/// A Launchpad model, recognized by its port names
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Model {
+    Launchpad,
+    S,
+    Mini,
+    Mk2,
+    Pro,
+    X,
+    MiniMk3,
+    ProMk3,
+}
+
+/// Name fragments, most specific first
+const MODELS: [(&str, Model); 8] = [("launchpad mini mk3", Model::MiniMk3),
+                                    ("launchpad pro mk3", Model::ProMk3),
+                                    ("launchpad mk2", Model::Mk2),
+                                    ("launchpad mini", Model::Mini),
+                                    ("launchpad pro", Model::Pro),
+                                    ("launchpad x", Model::X),
+                                    ("launchpad s", Model::S),
+                                    ("launchpad", Model::Launchpad)];
+
+impl Model {
+    /// The model a port belongs to, from its name
+    pub fn detect(name: &str) -> Option<Model> {
+        let name = name.to_lowercase();
+        MODELS.iter()
+            .find(|&&(fragment, _)| {
+                // The fragment must end at a word boundary, so "Launchpad
+                // Xtra" is not an X
+                name.match_indices(fragment).any(|(i, _)| {
+                    !name[i + fragment.len()..].starts_with(|c: char| c.is_alphanumeric())
+                })
+            })
+            .map(|&(_, model)| model)
+    }
+
+    /// The model's product name
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Model::Launchpad => "Launchpad",
+            Model::S => "Launchpad S",
+            Model::Mini => "Launchpad Mini",
+            Model::Mk2 => "Launchpad Mk2",
+            Model::Pro => "Launchpad Pro",
+            Model::X => "Launchpad X",
+            Model::MiniMk3 => "Launchpad Mini Mk3",
+            Model::ProMk3 => "Launchpad Pro Mk3",
+        }
+    }
+
+    /// Whether `LaunchpadMk2` can drive this model
+    pub fn supported(&self) -> bool {
+        *self == Model::Mk2
+    }
+}
+
+/// A MIDI device with its ports. PortMidi lists a device's inputs and outputs
+/// as separate devices of the same name; they are gathered here. Several
+/// devices may share a name, such as two Launchpads of the same model, so the
+/// first input of a name is paired with its first output, the second with the
+/// second, and so on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MidiDevice {
+    pub name: String,
+    pub model: Option<Model>,
+    /// PortMidi device ids of the inputs
+    pub inputs: Vec<i32>,
+    /// PortMidi device ids of the outputs
+    pub outputs: Vec<i32>,
+}
+
+/// The devices PortMidi reports, in the order their first port is listed
+pub fn midi_devices(devs: &[pm::DeviceInfo]) -> Vec<MidiDevice> {
+    group(devs.iter().map(|d| (d.id(), d.name().as_str(), d.is_input())))
+}
+
+fn group<'a, I: Iterator<Item = (i32, &'a str, bool)>>(ports: I) -> Vec<MidiDevice> {
+    let mut devices: Vec<MidiDevice> = Vec::new();
+    for (id, name, input) in ports {
+        // The first device of this name still missing a port of this kind
+        let index = match devices.iter().position(|d| {
+            d.name == name && if input { d.inputs.is_empty() } else { d.outputs.is_empty() }
+        }) {
+            Some(index) => index,
+            None => {
+                devices.push(MidiDevice {
+                    name: name.to_string(),
+                    model: Model::detect(name),
+                    inputs: Vec::new(),
+                    outputs: Vec::new(),
+                });
+                devices.len() - 1
+            }
+        };
+        if input {
+            devices[index].inputs.push(id);
+        } else {
+            devices[index].outputs.push(id);
+        }
+    }
+    devices
+}
+
+/// The input and output ids `LaunchpadMk2::guess_from` opens: the first
+/// input and the first output of a Launchpad Mk2
+pub fn guess_ports(devs: &[pm::DeviceInfo]) -> (Option<i32>, Option<i32>) {
+    let mk2 = |d: &&pm::DeviceInfo| Model::detect(d.name()) == Some(Model::Mk2);
+    let input = devs.iter().filter(mk2).find(|d| d.is_input()).map(|d| d.id());
+    let output = devs.iter().filter(mk2).find(|d| d.is_output()).map(|d| d.id());
+    (input, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models() {
+        assert_eq!(Model::detect("Launchpad MK2"), Some(Model::Mk2));
+        assert_eq!(Model::detect("Launchpad MK2 MIDI 1"), Some(Model::Mk2));
+        assert_eq!(Model::detect("MIDIIN2 (Launchpad Pro)"), Some(Model::Pro));
+        assert_eq!(Model::detect("Launchpad Mini MK3 LPMiniMK3 MIDI"), Some(Model::MiniMk3));
+        assert_eq!(Model::detect("Launchpad X LPX MIDI"), Some(Model::X));
+        assert_eq!(Model::detect("Launchpad S"), Some(Model::S));
+        assert_eq!(Model::detect("Launchpad Sequencer"), Some(Model::Launchpad));
+        assert_eq!(Model::detect("Midi Through Port-0"), None);
+    }
+
+    #[test]
+    fn grouping() {
+        let devices = group(vec![(0, "Midi Through", false),
+                                 (1, "Launchpad MK2", true),
+                                 (2, "Midi Through", true),
+                                 (3, "Launchpad MK2", false)]
+            .into_iter());
+        assert_eq!(devices.len(), 2);
+        assert_eq!((&devices[0].inputs, &devices[0].outputs), (&vec![2], &vec![0]));
+        assert_eq!(devices[1].model, Some(Model::Mk2));
+        assert_eq!((&devices[1].inputs, &devices[1].outputs), (&vec![1], &vec![3]));
+    }
+
+    #[test]
+    fn identical_devices() {
+        let devices = group(vec![(0, "Launchpad MK2", true),
+                                 (1, "Launchpad MK2", true),
+                                 (2, "Launchpad MK2", false),
+                                 (3, "Launchpad MK2", false),
+                                 (4, "Launchpad MK2", true)]
+            .into_iter());
+        assert_eq!(devices.len(), 3);
+        assert_eq!((&devices[0].inputs, &devices[0].outputs), (&vec![0], &vec![2]));
+        assert_eq!((&devices[1].inputs, &devices[1].outputs), (&vec![1], &vec![3]));
+        assert_eq!((&devices[2].inputs, &devices[2].outputs), (&vec![4], &vec![]));
+    }
+}
@@ -0,0 +1,223 @@
+//! Conversion of RGB images to palette colors for the grid.
+//!
+//! Snapping each pad to its nearest palette color on its own produces banding
+//! in photos and gradients. Dithering spreads the quantization error across
+//! neighboring pads instead.
+
+use color::{nearest_palette, RGBColor, COLOR_PALETTE};
+use launchpad::Color;
+
+/// How to distribute the error between an RGB pixel and its palette color
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Dither {
+    /// Each pixel independently snaps to its nearest palette color
+    None,
+    /// Ordered dithering using a 4x4 Bayer threshold matrix
+    Ordered,
+    /// Floyd-Steinberg error diffusion
+    FloydSteinberg,
+}
+
+/// 4x4 Bayer threshold matrix
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Amplitude of the ordered dithering threshold, in 8 bit channel units. The
+/// palette is coarse, so this is much larger than for an evenly spaced one.
+const ORDERED_SPREAD: f32 = 48.0;
+
+/// Scale an image of `width` x `height` pixels (row-major, top row first) to
+/// `grid_width` x `grid_height` pixels. Each output pixel is the average of
+/// the source pixels it covers, or the nearest source pixel when enlarging.
+pub fn downscale(pixels: &[RGBColor],
+                 width: usize,
+                 height: usize,
+                 grid_width: usize,
+                 grid_height: usize)
+                 -> Vec<RGBColor> {
+    assert_eq!(pixels.len(), width * height);
+    assert!(width > 0 && height > 0);
+
+    let mut out = Vec::with_capacity(grid_width * grid_height);
+    for gy in 0..grid_height {
+        let y0 = gy * height / grid_height;
+        let y1 = ((gy + 1) * height / grid_height).max(y0 + 1);
+        for gx in 0..grid_width {
+            let x0 = gx * width / grid_width;
+            let x1 = ((gx + 1) * width / grid_width).max(x0 + 1);
+
+            let mut sum = [0u32; 3];
+            for y in y0..y1 {
+                for p in &pixels[y * width + x0..y * width + x1] {
+                    sum[0] += p.red() as u32;
+                    sum[1] += p.green() as u32;
+                    sum[2] += p.blue() as u32;
+                }
+            }
+            let count = ((y1 - y0) * (x1 - x0)) as u32;
+            out.push(RGBColor::new((sum[0] / count) as u8,
+                                   (sum[1] / count) as u8,
+                                   (sum[2] / count) as u8));
+        }
+    }
+    out
+}
+
+/// Cut the largest centered area with the aspect ratio `aspect_width` :
+/// `aspect_height` out of an image of `width` x `height` pixels (row-major,
+/// top row first). Returns the cropped pixels and their width and height.
+/// Panics if either side of the aspect ratio is 0.
+pub fn crop_to_aspect(pixels: &[RGBColor],
+                      width: usize,
+                      height: usize,
+                      aspect_width: usize,
+                      aspect_height: usize)
+                      -> (Vec<RGBColor>, usize, usize) {
+    assert_eq!(pixels.len(), width * height);
+    assert!(aspect_width > 0 && aspect_height > 0, "Bad Aspect!");
+    let (crop_width, crop_height) = if width * aspect_height > height * aspect_width {
+        ((height * aspect_width / aspect_height).max(1), height)
+    } else {
+        (width, (width * aspect_height / aspect_width).max(1))
+    };
+    let left = (width - crop_width) / 2;
+    let top = (height - crop_height) / 2;
+
+    let mut out = Vec::with_capacity(crop_width * crop_height);
+    for y in top..top + crop_height {
+        out.extend_from_slice(&pixels[y * width + left..y * width + left + crop_width]);
+    }
+    (out, crop_width, crop_height)
+}
+
+/// Convert an image of `width` x `height` pixels (row-major, top row first)
+/// to `grid_width` x `grid_height` palette colors, downscaling first if the
+/// image is a different size than the grid.
+pub fn dither_image(pixels: &[RGBColor],
+                    width: usize,
+                    height: usize,
+                    grid_width: usize,
+                    grid_height: usize,
+                    dither: Dither)
+                    -> Vec<Color> {
+    let scaled;
+    let pixels = if width == grid_width && height == grid_height {
+        assert_eq!(pixels.len(), width * height);
+        pixels
+    } else {
+        scaled = downscale(pixels, width, height, grid_width, grid_height);
+        &scaled[..]
+    };
+
+    match dither {
+        Dither::None => pixels.iter().map(|p| nearest_palette(p.red(), p.green(), p.blue())).collect(),
+        Dither::Ordered => ordered(pixels, grid_width),
+        Dither::FloydSteinberg => floyd_steinberg(pixels, grid_width, grid_height),
+    }
+}
+
+fn clamp_channel(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+fn ordered(pixels: &[RGBColor], width: usize) -> Vec<Color> {
+    pixels.iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let threshold = BAYER[(i / width) % 4][(i % width) % 4] as f32;
+            let offset = ((threshold + 0.5) / 16.0 - 0.5) * ORDERED_SPREAD;
+            nearest_palette(clamp_channel(p.red() as f32 + offset),
+                            clamp_channel(p.green() as f32 + offset),
+                            clamp_channel(p.blue() as f32 + offset))
+        })
+        .collect()
+}
+
+fn floyd_steinberg(pixels: &[RGBColor], width: usize, height: usize) -> Vec<Color> {
+    let mut work: Vec<[f32; 3]> = pixels.iter()
+        .map(|p| [p.red() as f32, p.green() as f32, p.blue() as f32])
+        .collect();
+    let mut out = Vec::with_capacity(work.len());
+
+    for y in 0..height {
+        for x in 0..width {
+            let old = work[y * width + x];
+            let index = nearest_palette(clamp_channel(old[0]),
+                                        clamp_channel(old[1]),
+                                        clamp_channel(old[2]));
+            let new = COLOR_PALETTE[index as usize];
+            let error = [old[0] - new.red() as f32,
+                         old[1] - new.green() as f32,
+                         old[2] - new.blue() as f32];
+            out.push(index);
+
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                let ny = y + dy;
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    return;
+                }
+                let cell = &mut work[ny * width + nx as usize];
+                for c in 0..3 {
+                    cell[c] += error[c] * weight;
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downscale_averages_covered_pixels() {
+        let black = RGBColor::new(0, 0, 0);
+        let white = RGBColor::new(200, 100, 50);
+        let pixels = [black, white, white, black];
+        assert_eq!(downscale(&pixels, 2, 2, 1, 1), vec![RGBColor::new(100, 50, 25)]);
+    }
+
+    #[test]
+    fn palette_colors_survive_error_diffusion() {
+        let pixels = vec![COLOR_PALETTE[5]; 64];
+        for &mode in &[Dither::None, Dither::FloydSteinberg] {
+            assert!(dither_image(&pixels, 8, 8, 8, 8, mode).iter().all(|&c| c == 5),
+                    "{:?}",
+                    mode);
+        }
+    }
+
+    #[test]
+    fn ordered_dithering_mixes_palette_colors() {
+        let gray = vec![RGBColor::new(0x5a, 0x5a, 0x5a); 64];
+        let distinct = |colors: Vec<u8>| {
+            let mut colors = colors;
+            colors.sort();
+            colors.dedup();
+            colors.len()
+        };
+        assert_eq!(distinct(dither_image(&gray, 8, 8, 8, 8, Dither::None)), 1);
+        assert!(distinct(dither_image(&gray, 8, 8, 8, 8, Dither::Ordered)) > 1);
+    }
+
+    #[test]
+    fn crop_keeps_the_center() {
+        let pixels: Vec<RGBColor> = (0..6).map(|i| RGBColor::new(i, 0, 0)).collect();
+        let (cropped, width, height) = crop_to_aspect(&pixels, 3, 2, 1, 1);
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(cropped, vec![pixels[0], pixels[1], pixels[3], pixels[4]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bad Aspect!")]
+    fn crop_to_no_aspect() {
+        crop_to_aspect(&[RGBColor::new(0, 0, 0)], 1, 1, 0, 1);
+    }
+}
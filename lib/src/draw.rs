@@ -0,0 +1,344 @@
+//! Drawing on the pads of a `Frame`.
+//!
+//! Coordinates are signed so shapes may hang off the grid; anything outside
+//! the 8x8 pads is clipped. The top and side buttons are never touched.
+
+use color::LedColor;
+use frame::{Frame, GRID_SIZE};
+
+/// A small picture to `blit` onto a frame. `None` pixels are transparent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sprite {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major, top row first
+    pub pixels: Vec<Option<LedColor>>,
+}
+
+impl Sprite {
+    /// A fully transparent sprite
+    pub fn new(width: usize, height: usize) -> Sprite {
+        Sprite {
+            width,
+            height,
+            pixels: vec![None; width * height],
+        }
+    }
+
+    /// Build a sprite from rows of characters, each looked up in `colors`.
+    /// Characters missing from `colors` are transparent.
+    ///
+    /// ```
+    /// use launchpad::*;
+    ///
+    /// let heart = Sprite::from_art(&[".r.r.",
+    ///                                "rrrrr",
+    ///                                ".rrr.",
+    ///                                "..r.."],
+    ///                              &[('r', LedColor::Palette(palette::RED))]);
+    /// assert_eq!(heart.width, 5);
+    /// ```
+    pub fn from_art(rows: &[&str], colors: &[(char, LedColor)]) -> Sprite {
+        let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+        let mut sprite = Sprite::new(width, rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                sprite.pixels[y * width + x] = colors.iter().find(|&&(k, _)| k == c).map(|&(_, color)| color);
+            }
+        }
+        sprite
+    }
+}
+
+fn on_grid(x: isize, y: isize) -> bool {
+    x >= 0 && y >= 0 && x < GRID_SIZE as isize && y < GRID_SIZE as isize
+}
+
+/// The part of `from..to` on the grid
+fn clip(from: isize, to: isize) -> ::std::ops::Range<isize> {
+    from.max(0)..to.min(GRID_SIZE as isize)
+}
+
+/// How far apart two coordinates are, without overflowing
+fn distance(a: isize, b: isize) -> u128 {
+    (a as i128 - b as i128).unsigned_abs()
+}
+
+/// The points of a line from `(a0, b0)` to `(a1, b1)` which changes at
+/// least as fast in `a` as in `b`, for each `a` on the grid. Rounding halves
+/// away from the start picks the same points as Bresenham's algorithm.
+fn steps(a0: isize, b0: isize, a1: isize, b1: isize) -> impl Iterator<Item = (isize, isize)> {
+    let (da, db) = (a1 as i128 - a0 as i128, b1 as i128 - b0 as i128);
+    clip(a0.min(a1), a0.max(a1).saturating_add(1)).map(move |a| {
+        // |db| and the distance along `a` are both at most |da|, so their
+        // product fits
+        let along = db.unsigned_abs() * distance(a, a0);
+        let d = da.unsigned_abs();
+        let offset = along.checked_div(d).map_or(0, |q| q + (2 * (along % d) >= d) as u128) as i128;
+        (a, (b0 as i128 + db.signum() * offset) as isize)
+    })
+}
+
+impl Frame {
+    /// Set the pad at `(x, y)`, ignoring points off the grid
+    pub fn set_pixel<C: Into<LedColor>>(&mut self, x: isize, y: isize, color: C) {
+        if on_grid(x, y) {
+            self.set_pad(x as usize, y as usize, color);
+        }
+    }
+
+    /// Draw a line between two points, inclusive, picking the pads
+    /// Bresenham's algorithm would
+    pub fn line<C: Into<LedColor>>(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: C) {
+        let color = color.into();
+        if distance(x0, x1) >= distance(y0, y1) {
+            for (x, y) in steps(x0, y0, x1, y1) {
+                self.set_pixel(x, y, color);
+            }
+        } else {
+            for (y, x) in steps(y0, x0, y1, x1) {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Outline a rectangle with its top left corner at `(x, y)`
+    pub fn rect<C: Into<LedColor>>(&mut self, x: isize, y: isize, width: isize, height: isize, color: C) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let color = color.into();
+        let (right, bottom) = (x.saturating_add(width - 1), y.saturating_add(height - 1));
+        self.line(x, y, right, y, color);
+        self.line(x, bottom, right, bottom, color);
+        self.line(x, y, x, bottom, color);
+        self.line(right, y, right, bottom, color);
+    }
+
+    /// Fill a rectangle with its top left corner at `(x, y)`
+    pub fn fill_rect<C: Into<LedColor>>(&mut self, x: isize, y: isize, width: isize, height: isize, color: C) {
+        let color = color.into();
+        for py in clip(y, y.saturating_add(height)) {
+            for px in clip(x, x.saturating_add(width)) {
+                self.set_pad(px as usize, py as usize, color);
+            }
+        }
+    }
+
+    /// Outline a circle around `(cx, cy)`, picking the pads the midpoint
+    /// algorithm would
+    pub fn circle<C: Into<LedColor>>(&mut self, cx: isize, cy: isize, radius: isize, color: C) {
+        if radius < 0 {
+            return;
+        }
+        let color = color.into();
+        let r = radius as u128;
+        for y in 0..GRID_SIZE {
+            for x in 0..GRID_SIZE {
+                let (dx, dy) = (distance(x as isize, cx), distance(y as isize, cy));
+                let (a, b) = (dx.max(dy), dx.min(dy));
+                // Stepping `b` through an octant, the algorithm keeps the `a`
+                // with a² - a + b² < r² <= a² + a + b²
+                if a <= r && (r == 0 || (a * a - a + b * b < r * r && a * a + a + b * b >= r * r)) {
+                    self.set_pad(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Fill a circle around `(cx, cy)`
+    pub fn fill_circle<C: Into<LedColor>>(&mut self, cx: isize, cy: isize, radius: isize, color: C) {
+        if radius < 0 {
+            return;
+        }
+        let color = color.into();
+        let r = radius as u128;
+        for y in 0..GRID_SIZE {
+            for x in 0..GRID_SIZE {
+                let (dx, dy) = (distance(x as isize, cx), distance(y as isize, cy));
+                if dx <= r && dy <= r && dx * dx + dy * dy <= r * r + r {
+                    self.set_pad(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Fill the area of same-colored pads connected to `(x, y)` with a color
+    pub fn flood_fill<C: Into<LedColor>>(&mut self, x: isize, y: isize, color: C) {
+        let color = color.into();
+        if !on_grid(x, y) {
+            return;
+        }
+        let target = self.pad(x as usize, y as usize);
+        if target == color {
+            return;
+        }
+
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            if !on_grid(x, y) || self.pad(x as usize, y as usize) != target {
+                continue;
+            }
+            self.set_pad(x as usize, y as usize, color);
+            stack.extend_from_slice(&[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+        }
+    }
+
+    /// Draw a sprite with its top left corner at `(x, y)`, skipping its
+    /// transparent pixels
+    pub fn blit(&mut self, sprite: &Sprite, x: isize, y: isize) {
+        for sy in 0..sprite.height {
+            for sx in 0..sprite.width {
+                if let Some(color) = sprite.pixels[sy * sprite.width + sx] {
+                    self.set_pixel(x.saturating_add(sx as isize), y.saturating_add(sy as isize), color);
+                }
+            }
+        }
+    }
+
+    /// Move the pads' contents by `(dx, dy)`, filling the uncovered pads
+    pub fn shift<C: Into<LedColor>>(&mut self, dx: isize, dy: isize, fill: C) {
+        let fill = fill.into();
+        let old = self.clone();
+        for y in 0..GRID_SIZE as isize {
+            for x in 0..GRID_SIZE as isize {
+                let (ox, oy) = (x - dx, y - dy);
+                let color = if on_grid(ox, oy) { old.pad(ox as usize, oy as usize) } else { fill };
+                self.set_pad(x as usize, y as usize, color);
+            }
+        }
+    }
+
+    /// Move the pads' contents by `(dx, dy)`, wrapping around the edges
+    pub fn rotate(&mut self, dx: isize, dy: isize) {
+        let old = self.clone();
+        let size = GRID_SIZE as isize;
+        for y in 0..size {
+            for x in 0..size {
+                let (ox, oy) = ((x - dx).rem_euclid(size), (y - dy).rem_euclid(size));
+                self.set_pad(x as usize, y as usize, old.pad(ox as usize, oy as usize));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use palette;
+
+    fn lit(frame: &Frame) -> Vec<(usize, usize)> {
+        let mut lit = Vec::new();
+        for y in 0..GRID_SIZE {
+            for x in 0..GRID_SIZE {
+                if frame.pad(x, y) != LedColor::Off {
+                    lit.push((x, y));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn line_is_clipped() {
+        let mut frame = Frame::new();
+        frame.line(-2, -2, 2, 2, 5);
+        assert_eq!(lit(&frame), vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn flood_fill_stops_at_outline() {
+        let mut frame = Frame::new();
+        frame.rect(0, 0, 4, 4, 5);
+        frame.flood_fill(1, 1, 9);
+        assert_eq!(frame.pad(2, 2), LedColor::Palette(9));
+        assert_eq!(frame.pad(5, 5), LedColor::Off);
+    }
+
+    #[test]
+    fn huge_lines_are_clipped() {
+        let mut frame = Frame::new();
+        frame.line(-(1 << 40), 3, 1 << 40, 3, 5);
+        assert_eq!(lit(&frame), (0..GRID_SIZE).map(|x| (x, 3)).collect::<Vec<_>>());
+        frame.line(isize::MIN, isize::MIN, isize::MAX, isize::MAX, 5);
+        assert_eq!(frame.pad(4, 4), LedColor::Palette(5));
+    }
+
+    #[test]
+    fn fill_rect_is_clipped() {
+        let mut frame = Frame::new();
+        frame.fill_rect(6, -1, 3, 3, 5);
+        assert_eq!(lit(&frame), vec![(6, 0), (7, 0), (6, 1), (7, 1)]);
+
+        let mut frame = Frame::new();
+        frame.fill_rect(0, 0, 1 << 40, 1, 5);
+        assert_eq!(lit(&frame).len(), GRID_SIZE);
+        frame.fill_rect(isize::MAX - 1, 0, 4, 1, 9);
+        frame.fill_rect(0, 0, 0, 4, 9);
+        assert_eq!(frame.pad(0, 0), LedColor::Palette(5));
+    }
+
+    #[test]
+    fn circles() {
+        let mut frame = Frame::new();
+        frame.circle(3, 3, 2, 5);
+        assert_eq!(lit(&frame),
+                   vec![(2, 1), (3, 1), (4, 1), (1, 2), (5, 2), (1, 3), (5, 3), (1, 4), (5, 4), (2, 5),
+                        (3, 5), (4, 5)]);
+
+        let mut frame = Frame::new();
+        frame.fill_circle(3, 3, 1, 5);
+        assert_eq!(lit(&frame).len(), 9);
+        frame.fill_circle(0, 0, isize::MAX, 5);
+        assert_eq!(lit(&frame).len(), GRID_SIZE * GRID_SIZE);
+
+        let mut frame = Frame::new();
+        frame.circle(0, 0, 1 << 40, 5);
+        frame.circle(3, 3, -1, 5);
+        frame.fill_circle(3, 3, -1, 5);
+        assert_eq!(lit(&frame), vec![]);
+        frame.circle(3, 3, 0, 5);
+        assert_eq!(lit(&frame), vec![(3, 3)]);
+    }
+
+    #[test]
+    fn blit_is_clipped() {
+        let red = LedColor::Palette(5);
+        let sprite = Sprite::from_art(&["r.", "rr"], &[('r', red)]);
+        let mut frame = Frame::new();
+        frame.blit(&sprite, 7, -1);
+        assert_eq!(lit(&frame), vec![(7, 0)]);
+        frame.blit(&sprite, -1, 6);
+        assert_eq!(lit(&frame), vec![(7, 0), (0, 7)]);
+        frame.blit(&sprite, isize::MAX, isize::MAX);
+        assert_eq!(lit(&frame).len(), 2);
+    }
+
+    #[test]
+    fn flood_fill_treats_every_off_alike() {
+        let mut frame = Frame::new();
+        frame.fill_rect(0, 0, 4, GRID_SIZE as isize, palette::OFF);
+        frame.flood_fill(0, 0, 5);
+        assert_eq!(lit(&frame).len(), GRID_SIZE * GRID_SIZE);
+    }
+
+    #[test]
+    fn rotate_wraps_around() {
+        let mut frame = Frame::new();
+        frame.set_pad(7, 0, 5);
+        frame.rotate(1, -1);
+        assert_eq!(lit(&frame), vec![(0, 7)]);
+        frame.rotate(-9, 17);
+        assert_eq!(lit(&frame), vec![(7, 0)]);
+    }
+
+    #[test]
+    fn shift_fills_uncovered_pads() {
+        let mut frame = Frame::new();
+        frame.set_pad(7, 0, 5);
+        frame.shift(1, 0, 3);
+        assert_eq!(frame.pad(0, 0), LedColor::Palette(3));
+        assert_eq!(lit(&frame).len(), GRID_SIZE);
+    }
+}
@@ -0,0 +1,630 @@
+//! A software Launchpad Mark 2.
+//!
+//! A `Screen` understands the messages `LaunchpadMk2` sends: palette, RGB,
+//! row, column and whole-grid colors, flashing, pulsing, MIDI clock and
+//! scrolling text. It can render what a real device would show as truecolor
+//! ANSI text. An `Emulator` puts a `Screen` behind the ports of a
+//! `LaunchpadMk2`, and turns presses and releases into the messages a real
+//! device would send back, so pad applications can run without hardware. A
+//! `Mirror` keeps a `Screen` alongside a real device instead, to see what a
+//! program has lit.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use pm;
+use calibration::RGB_CHANNEL_MAX;
+use color::{LedColor, RGBColor};
+use frame::{Frame, Location, GRID_SIZE};
+use launchpad::{assert_position, column_positions, row_positions, LaunchpadMk2, MidiInput, MidiOutput};
+use scroll::ScrollSpeed;
+use text::{Direction, Scroller, Text};
+
+/// Header of the sysex messages to and from a Mark 2
+const SYSEX_HEADER: [u8; 6] = [0xF0, 0x00, 0x20, 0x29, 0x02, 0x18];
+
+/// One past the highest LED position
+const POSITIONS: usize = 112;
+
+/// How an LED is lit besides its color
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Effect {
+    /// Alternating between the LED's color and this one, in time with the
+    /// clock
+    Flash(LedColor),
+    /// Fading this color in and out, in time with the clock
+    Pulse(LedColor),
+}
+
+/// The state of one LED
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LedState {
+    pub color: LedColor,
+    pub effect: Option<Effect>,
+}
+
+/// Text being scrolled by the device
+#[derive(Debug, Clone)]
+struct Scroll {
+    scroller: Scroller,
+    updated: Instant,
+}
+
+/// What a Mark 2 shows, kept up to date from the messages sent to it
+#[derive(Debug, Clone)]
+pub struct Screen {
+    leds: [LedState; POSITIONS],
+    scroll: Option<Scroll>,
+    bpm: f64,
+    /// Start of the beat being measured from MIDI clock, and ticks into it
+    beat: Option<(Instant, u32)>,
+    last_tick: Option<Instant>,
+    started: Instant,
+    scrolls_finished: usize,
+}
+
+impl Default for Screen {
+    fn default() -> Screen {
+        Screen::new()
+    }
+}
+
+impl Screen {
+    /// A screen with every LED off, at the device's default 120 BPM
+    pub fn new() -> Screen {
+        Screen {
+            leds: [LedState::default(); POSITIONS],
+            scroll: None,
+            bpm: 120.0,
+            beat: None,
+            last_tick: None,
+            started: Instant::now(),
+            scrolls_finished: 0,
+        }
+    }
+
+    /// The state of the LED at a position. Panics if there is no LED there,
+    /// like the lighting calls.
+    pub fn led(&self, position: u8) -> LedState {
+        assert_position(position);
+        self.leds[position as usize]
+    }
+
+    /// The color of the LED at a position, if there is one
+    fn color(&self, position: u8) -> Option<LedColor> {
+        Location::of(position).map(|_| self.leds[position as usize].color)
+    }
+
+    /// The tempo of flashing and pulsing, in beats per minute
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Whether text is scrolling
+    pub fn scrolling(&self) -> bool {
+        self.scroll.is_some()
+    }
+
+    /// Take in a short message
+    pub fn receive_message(&mut self, msg: [u8; 3]) {
+        match msg {
+            [0x90, position, color] | [0xB0, position, color] => {
+                self.set(position, LedColor::Palette(color), None)
+            }
+            [0x91, position, color] => {
+                let base = self.color(position).unwrap_or_default();
+                self.set(position, base, Some(Effect::Flash(LedColor::Palette(color))))
+            }
+            [0x92, position, color] => {
+                self.set(position, LedColor::Off, Some(Effect::Pulse(LedColor::Palette(color))))
+            }
+            [0xF8, _, _] => self.tick(Instant::now()),
+            _ => {}
+        }
+    }
+
+    /// Take in a sysex message
+    pub fn receive_sysex(&mut self, msg: &[u8]) {
+        if !msg.starts_with(&SYSEX_HEADER) || msg.len() < SYSEX_HEADER.len() + 2 {
+            return;
+        }
+        let command = msg[SYSEX_HEADER.len()];
+        let data = &msg[SYSEX_HEADER.len() + 1..msg.len() - 1];
+        let palette = |color: u8| LedColor::Palette(color);
+
+        match command {
+            0x0A => {
+                for led in data.chunks_exact(2) {
+                    self.set(led[0], palette(led[1]), None);
+                }
+            }
+            0x0B => {
+                for led in data.chunks_exact(4) {
+                    let channel = |v: u8| (v.min(RGB_CHANNEL_MAX) as u16 * 255 / RGB_CHANNEL_MAX as u16) as u8;
+                    let color = RGBColor::new(channel(led[1]), channel(led[2]), channel(led[3]));
+                    self.set(led[0], LedColor::Rgb(color), None);
+                }
+            }
+            0x0C => {
+                for column in data.chunks_exact(2).filter(|c| c[0] <= 8) {
+                    for position in column_positions(column[0]) {
+                        self.set(position, palette(column[1]), None);
+                    }
+                }
+            }
+            0x0D => {
+                for row in data.chunks_exact(2).filter(|r| r[0] <= 8) {
+                    for position in row_positions(row[0]) {
+                        self.set(position, palette(row[1]), None);
+                    }
+                }
+            }
+            0x0E if !data.is_empty() => {
+                for position in (0..9).flat_map(row_positions) {
+                    self.set(position, palette(data[0]), None);
+                }
+            }
+            0x23 => {
+                for led in data.chunks_exact(2) {
+                    let base = self.color(led[0]).unwrap_or_default();
+                    self.set(led[0], base, Some(Effect::Flash(palette(led[1]))));
+                }
+            }
+            0x28 => {
+                for led in data.chunks_exact(2) {
+                    self.set(led[0], LedColor::Off, Some(Effect::Pulse(palette(led[1]))));
+                }
+            }
+            0x14 if data.len() >= 2 => self.start_scroll(data[0], data[1] != 0, &data[2..]),
+            _ => {}
+        }
+    }
+
+    fn set(&mut self, position: u8, color: LedColor, effect: Option<Effect>) {
+        if Location::of(position).is_some() {
+            self.leds[position as usize] = LedState { color, effect };
+        }
+    }
+
+    fn tick(&mut self, now: Instant) {
+        self.last_tick = Some(now);
+        self.beat = match self.beat {
+            Some((start, ticks)) if ticks + 1 == 24 => {
+                self.bpm = 60.0 / (now - start).as_secs_f64();
+                Some((now, 0))
+            }
+            Some((start, ticks)) => Some((start, ticks + 1)),
+            None => Some((now, 0)),
+        };
+    }
+
+    fn start_scroll(&mut self, color: u8, looping: bool, text: &[u8]) {
+        self.scroll = None;
+        if text.is_empty() {
+            return;
+        }
+        // The device blanks the pads for the text
+        for position in (0..8).flat_map(row_positions).filter(|p| p % 10 != 9) {
+            self.set(position, LedColor::Off, None);
+        }
+
+        let mut speed = ScrollSpeed::Normal as u8;
+        let mut chars = String::new();
+        for &byte in text {
+            match byte {
+                // Speed changes apply to the whole message here
+                1..=7 if chars.is_empty() => speed = byte,
+                1..=7 => {}
+                _ => chars.push(byte as char),
+            }
+        }
+        let text = Text::new().push(&chars, LedColor::Palette(color));
+        self.scroll = Some(Scroll {
+            scroller: Scroller::new(&text, Direction::Left, 2.0 * speed as f32).looping(looping),
+            updated: Instant::now(),
+        });
+    }
+
+    /// Move scrolling text along to `now`
+    pub fn advance(&mut self, now: Instant) {
+        let finished = match self.scroll {
+            Some(ref mut scroll) => {
+                scroll.scroller.advance(now.saturating_duration_since(scroll.updated));
+                scroll.updated = now;
+                scroll.scroller.finished()
+            }
+            None => false,
+        };
+        if finished {
+            self.scroll = None;
+            self.scrolls_finished += 1;
+        }
+    }
+
+    /// The number of scrolls which finished since last asked
+    fn take_scrolls_finished(&mut self) -> usize {
+        let finished = self.scrolls_finished;
+        self.scrolls_finished = 0;
+        finished
+    }
+
+    /// How far through the current beat `now` is, from 0 to 1. Follows MIDI
+    /// clock while it arrives.
+    fn beat_phase(&self, now: Instant) -> f64 {
+        match (self.beat, self.last_tick) {
+            (Some((_, ticks)), Some(last)) if now - last < Duration::from_secs(1) => ticks as f64 / 24.0,
+            _ => (now - self.started).as_secs_f64() * self.bpm / 60.0 % 1.0,
+        }
+    }
+
+    /// The color the LED at a position shows at `now`, with flashing,
+    /// pulsing and scrolling text
+    pub fn shown(&self, position: u8, now: Instant) -> RGBColor {
+        if let (Some(scroll), Some(Location::Pad { x, y })) = (self.scroll.as_ref(), Location::of(position)) {
+            let mut frame = Frame::new();
+            scroll.scroller.draw(&mut frame);
+            return frame.pad(x, y).rgb();
+        }
+
+        let led = self.led(position);
+        let phase = self.beat_phase(now);
+        match led.effect {
+            None => led.color.rgb(),
+            Some(Effect::Flash(color)) => if phase < 0.5 { color.rgb() } else { led.color.rgb() },
+            Some(Effect::Pulse(color)) => {
+                let level = 0.5 - 0.5 * (phase * 2.0 * ::std::f64::consts::PI).cos();
+                let rgb = color.rgb();
+                let scale = |v: u8| (v as f64 * level).round() as u8;
+                RGBColor::new(scale(rgb.red()), scale(rgb.green()), scale(rgb.blue()))
+            }
+        }
+    }
+
+    /// Render the device as 9 lines of truecolor ANSI text, top buttons
+    /// first. Each LED takes three columns; see `location_at`. The column to
+    /// the right of an LED marks a `cursor` with `◀`, and flashing and
+    /// pulsing LEDs with `*` and `~`.
+    pub fn render(&self, now: Instant, cursor: Option<Location>) -> String {
+        let mut out = String::new();
+        for row in 0..GRID_SIZE + 1 {
+            for column in 0..GRID_SIZE + 1 {
+                let location = location_of(column, row);
+                let glyph = match location {
+                    Some(Location::Pad { .. }) => "██",
+                    Some(_) => "▄▄",
+                    None => "  ",
+                };
+                if let Some(location) = location {
+                    let color = self.shown(location.position(), now);
+                    // Keep LEDs which are off visible
+                    let (r, g, b) = if color == RGBColor::new(0, 0, 0) {
+                        (40, 40, 40)
+                    } else {
+                        (color.red(), color.green(), color.blue())
+                    };
+                    out.push_str(&format!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, glyph));
+                } else {
+                    out.push_str(glyph);
+                }
+                let effect = location.and_then(|l| self.led(l.position()).effect);
+                out.push_str(match effect {
+                    _ if location.is_some() && location == cursor => "◀",
+                    Some(Effect::Flash(_)) => "*",
+                    Some(Effect::Pulse(_)) => "~",
+                    None => " ",
+                });
+            }
+            out.push_str("\r\n");
+        }
+        out
+    }
+}
+
+/// The LED at a column and row of `Screen::render`'s grid of LEDs
+fn location_of(column: usize, row: usize) -> Option<Location> {
+    match (column, row) {
+        (GRID_SIZE, 0) => None,
+        (x, 0) if x < GRID_SIZE => Some(Location::Top(x)),
+        (GRID_SIZE, y) if y <= GRID_SIZE => Some(Location::Side(y - 1)),
+        (x, y) if x < GRID_SIZE && y <= GRID_SIZE => Some(Location::Pad { x, y: y - 1 }),
+        _ => None,
+    }
+}
+
+/// The LED drawn at a terminal column and line of `Screen::render`'s output,
+/// counting from 0 at its top left
+pub fn location_at(column: usize, line: usize) -> Option<Location> {
+    if column % 3 == 2 {
+        return None;
+    }
+    location_of(column / 3, line)
+}
+
+/// A software device. See the module documentation.
+pub struct Emulator {
+    screen: Arc<Mutex<Screen>>,
+    input: Sender<pm::MidiEvent>,
+}
+
+impl Emulator {
+    /// Start an emulator, with a `LaunchpadMk2` driving it
+    pub fn new() -> (Emulator, LaunchpadMk2) {
+        let screen = Arc::new(Mutex::new(Screen::new()));
+        let (input, events) = mpsc::channel();
+        let lpad = LaunchpadMk2::from_ports(EmulatorInput {
+                                                screen: screen.clone(),
+                                                events,
+                                            },
+                                            EmulatorOutput { screen: screen.clone() });
+        (Emulator { screen, input }, lpad)
+    }
+
+    /// What the emulated device shows
+    pub fn screen(&self) -> MutexGuard<'_, Screen> {
+        self.screen.lock().expect("Screen lock poisoned")
+    }
+
+    /// Render the emulated device as it is now. See `Screen::render`.
+    pub fn render(&self, cursor: Option<Location>) -> String {
+        let now = Instant::now();
+        let mut screen = self.screen();
+        screen.advance(now);
+        screen.render(now, cursor)
+    }
+
+    /// Press a button, as hard as `velocity` (1...127). The top and side
+    /// buttons of a real device always send 127.
+    pub fn press(&self, location: Location, velocity: u8) {
+        let velocity = match location {
+            Location::Pad { .. } => velocity.clamp(1, 127),
+            _ => 127,
+        };
+        self.send(location, velocity);
+    }
+
+    /// Release a button
+    pub fn release(&self, location: Location) {
+        self.send(location, 0);
+    }
+
+    fn send(&self, location: Location, velocity: u8) {
+        // Like the device, note messages for the pads and side buttons and
+        // control changes for the top buttons
+        let status = match location {
+            Location::Top(_) => 0xB0,
+            _ => 0x90,
+        };
+        // Nobody listening is no reason to fail
+        let _ = self.input.send(event([status, location.position(), velocity]));
+    }
+}
+
+/// A shadow copy of what a real device shows, kept from the messages sent
+/// to it, along with the button pressed last
+pub struct Mirror {
+    screen: Arc<Mutex<Screen>>,
+    pressed: Arc<Mutex<Option<(Location, u8)>>>,
+}
+
+impl Mirror {
+    /// Start mirroring everything sent to and received from a device
+    pub fn attach(lpad: &mut LaunchpadMk2) -> Mirror {
+        let mirror = Mirror {
+            screen: Arc::new(Mutex::new(Screen::new())),
+            pressed: Arc::new(Mutex::new(None)),
+        };
+        let (screen, pressed) = (mirror.screen.clone(), mirror.pressed.clone());
+        lpad.wrap_ports(|inner| Box::new(MirrorInput { inner, pressed }),
+                        |inner| Box::new(MirrorOutput { inner, screen }));
+        mirror
+    }
+
+    /// What the device shows
+    pub fn screen(&self) -> MutexGuard<'_, Screen> {
+        self.screen.lock().expect("Screen lock poisoned")
+    }
+
+    /// The button pressed last and how hard, if any has been
+    pub fn last_pressed(&self) -> Option<(Location, u8)> {
+        *self.pressed.lock().expect("Mirror lock poisoned")
+    }
+
+    /// Render what the device shows as it is now, marking the button
+    /// pressed last, followed by a line saying which it was and the tempo.
+    /// See `Screen::render`.
+    pub fn render(&self) -> String {
+        let now = Instant::now();
+        let pressed = self.last_pressed();
+        let mut screen = self.screen();
+        screen.advance(now);
+
+        let mut out = screen.render(now, pressed.map(|(location, _)| location));
+        let button = match pressed {
+            Some((Location::Pad { x, y }, velocity)) => format!("pad {}, {} at velocity {}", x, y, velocity),
+            Some((Location::Top(i), _)) => format!("top button {}", i),
+            Some((Location::Side(i), _)) => format!("side button {}", i),
+            None => "none".to_string(),
+        };
+        out.push_str(&format!("Last pressed: {}    Tempo: {:.0} BPM\r\n", button, screen.bpm()));
+        out
+    }
+}
+
+/// Passes messages on to a device, keeping a `Mirror`'s screen up to date
+struct MirrorOutput {
+    inner: Box<dyn MidiOutput>,
+    screen: Arc<Mutex<Screen>>,
+}
+
+impl MidiOutput for MirrorOutput {
+    fn write_message(&mut self, msg: [u8; 3]) -> pm::Result<()> {
+        self.inner.write_message(msg)?;
+        self.screen.lock().expect("Screen lock poisoned").receive_message(msg);
+        Ok(())
+    }
+
+    fn write_sysex(&mut self, msg: &[u8]) -> pm::Result<()> {
+        self.inner.write_sysex(msg)?;
+        self.screen.lock().expect("Screen lock poisoned").receive_sysex(msg);
+        Ok(())
+    }
+}
+
+/// Passes messages on from a device, noting presses for a `Mirror`
+struct MirrorInput {
+    inner: Box<dyn MidiInput>,
+    pressed: Arc<Mutex<Option<(Location, u8)>>>,
+}
+
+impl MidiInput for MirrorInput {
+    fn read_n(&self, max: usize) -> pm::Result<Option<Vec<pm::MidiEvent>>> {
+        let read = self.inner.read_n(max)?;
+        for event in read.iter().flatten() {
+            let msg = event.message;
+            if (msg.status == 0x90 || msg.status == 0xB0) && msg.data2 > 0 {
+                if let Some(location) = Location::of(msg.data1) {
+                    *self.pressed.lock().expect("Mirror lock poisoned") = Some((location, msg.data2));
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+fn event(msg: [u8; 3]) -> pm::MidiEvent {
+    pm::MidiEvent {
+        message: msg.into(),
+        timestamp: 0,
+    }
+}
+
+/// The device's end of the messages to an emulator
+struct EmulatorOutput {
+    screen: Arc<Mutex<Screen>>,
+}
+
+impl MidiOutput for EmulatorOutput {
+    fn write_message(&mut self, msg: [u8; 3]) -> pm::Result<()> {
+        self.screen.lock().expect("Screen lock poisoned").receive_message(msg);
+        Ok(())
+    }
+
+    fn write_sysex(&mut self, msg: &[u8]) -> pm::Result<()> {
+        self.screen.lock().expect("Screen lock poisoned").receive_sysex(msg);
+        Ok(())
+    }
+}
+
+/// The device's end of the messages from an emulator
+struct EmulatorInput {
+    screen: Arc<Mutex<Screen>>,
+    events: Receiver<pm::MidiEvent>,
+}
+
+impl MidiInput for EmulatorInput {
+    fn read_n(&self, max: usize) -> pm::Result<Option<Vec<pm::MidiEvent>>> {
+        let mut read: Vec<pm::MidiEvent> = self.events.try_iter().take(max).collect();
+
+        let mut screen = self.screen.lock().expect("Screen lock poisoned");
+        screen.advance(Instant::now());
+        for _ in 0..screen.take_scrolls_finished() {
+            // PortMidi delivers sysex four bytes at a time, of which
+            // `pm::MidiMessage` keeps three
+            read.push(event([0xF0, 0x00, 0x20]));
+            read.push(event([0x02, 0x18, 0x15]));
+        }
+
+        Ok(if read.is_empty() { None } else { Some(read) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::{Event, EventDecoder};
+    use palette;
+    use scroll::ScrollText;
+
+    #[test]
+    fn emulated_device() {
+        let (emulator, mut lpad) = Emulator::new();
+        lpad.set_led(11, palette::RED);
+        lpad.set_row(8, RGBColor::new(255, 0, 0));
+        lpad.set_column(0, palette::BLUE);
+        {
+            let screen = emulator.screen();
+            assert_eq!(screen.led(11).color, LedColor::Palette(palette::BLUE));
+            assert_eq!(screen.led(12).color, LedColor::Off);
+            assert_eq!(screen.led(105).color.rgb().red(), 255);
+        }
+
+        emulator.press(Location::Pad { x: 0, y: 0 }, 100);
+        emulator.release(Location::Top(2));
+        assert_eq!(lpad.poll_events(),
+                   vec![Event::Press {
+                            position: 81,
+                            velocity: 100,
+                        },
+                        Event::Release { position: 106 }]);
+    }
+
+    #[test]
+    fn mirror_follows_device() {
+        let (emulator, mut lpad) = Emulator::new();
+        let mirror = Mirror::attach(&mut lpad);
+        lpad.set_pulse(55, palette::CYAN);
+        assert_eq!(mirror.screen().led(55), emulator.screen().led(55));
+        assert_eq!(mirror.screen().led(55).effect, Some(Effect::Pulse(LedColor::Palette(palette::CYAN))));
+
+        emulator.press(Location::Side(3), 127);
+        lpad.poll_events();
+        assert_eq!(mirror.last_pressed(), Some((Location::Side(3), 127)));
+    }
+
+    #[test]
+    fn bad_positions_are_ignored() {
+        let mut screen = Screen::new();
+        screen.receive_message([0x90, 100, 5]);
+        screen.receive_message([0x91, 20, 5]);
+        screen.receive_sysex(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0A, 99, 5, 0xF7]);
+        let positions = (0..POSITIONS as u8).filter(|&p| Location::of(p).is_some());
+        assert!(positions.map(|p| screen.led(p)).all(|led| led == LedState::default()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bad Positon!")]
+    fn no_led_at_position() {
+        Screen::new().led(100);
+    }
+
+    #[test]
+    fn scroll_finishes() {
+        let mut screen = Screen::new();
+        screen.receive_sysex(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x14, 5, 0, 7, b'A', 0xF7]);
+        assert!(screen.scrolling());
+        screen.advance(Instant::now() + Duration::from_secs(10));
+        assert!(!screen.scrolling());
+        assert_eq!(screen.take_scrolls_finished(), 1);
+
+        let mut decoder = EventDecoder::new();
+        assert_eq!(decoder.decode(&[0xF0, 0x00, 0x20].into()), None);
+        assert_eq!(decoder.decode(&[0x02, 0x18, 0x15].into()), Some(Event::ScrollFinished));
+    }
+
+    #[test]
+    fn device_hears_scroll_finish() {
+        let (emulator, mut lpad) = Emulator::new();
+        let text = ScrollText::new().ascii("Hi!").unwrap();
+        lpad.scroll_text(palette::WHITE, false, text.as_str());
+        assert!(emulator.screen().scrolling());
+        assert_eq!(lpad.poll_events(), vec![]);
+
+        emulator.screen().advance(Instant::now() + Duration::from_secs(10));
+        assert_eq!(lpad.poll_events(), vec![Event::ScrollFinished]);
+        assert_eq!(lpad.poll_events(), vec![]);
+    }
+}
@@ -0,0 +1,89 @@
+//! Decoding of the messages a Launchpad Mark 2 sends.
+
+use pm;
+
+/// Something that happened on the device
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A button was pressed
+    Press { position: u8, velocity: u8 },
+    /// A button was released
+    Release { position: u8 },
+    /// A non-looping `scroll_text` message finished scrolling
+    ScrollFinished,
+}
+
+/// Turns raw `pm::MidiMessage`s from the device into `Event`s.
+///
+/// PortMidi delivers incoming sysex four bytes per message, and
+/// `pm::MidiMessage` only keeps the first three of them, so sysex is
+/// recognized from the bytes which survive: `F0 00 20` followed by
+/// `02 18 15` for the text-scroll-complete message `F0 00 20 29 02 18 15 F7`.
+#[derive(Debug, Default)]
+pub struct EventDecoder {
+    in_novation_sysex: bool,
+}
+
+impl EventDecoder {
+    pub fn new() -> EventDecoder {
+        EventDecoder::default()
+    }
+
+    /// Decode the next message, if it is one the device sends for an event
+    pub fn decode(&mut self, msg: &pm::MidiMessage) -> Option<Event> {
+        let sysex_start = self.in_novation_sysex;
+        self.in_novation_sysex = false;
+
+        match (msg.status, msg.data1, msg.data2) {
+            // Pads and side buttons: note on, channel 1
+            // Top buttons: control change, channel 1
+            (0x90, position, 0) | (0xB0, position, 0) => Some(Event::Release { position }),
+            (0x90, position, velocity) | (0xB0, position, velocity) => {
+                Some(Event::Press { position, velocity })
+            }
+            (0xF0, 0x00, 0x20) => {
+                self.in_novation_sysex = true;
+                None
+            }
+            (0x02, 0x18, 0x15) if sysex_start => Some(Event::ScrollFinished),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(decoder: &mut EventDecoder, msg: [u8; 3]) -> Option<Event> {
+        decoder.decode(&pm::MidiMessage::from(msg))
+    }
+
+    #[test]
+    fn buttons() {
+        let mut decoder = EventDecoder::new();
+        assert_eq!(decode(&mut decoder, [0x90, 11, 127]),
+                   Some(Event::Press { position: 11, velocity: 127 }));
+        assert_eq!(decode(&mut decoder, [0x90, 11, 0]), Some(Event::Release { position: 11 }));
+        assert_eq!(decode(&mut decoder, [0xB0, 104, 127]),
+                   Some(Event::Press { position: 104, velocity: 127 }));
+        assert_eq!(decode(&mut decoder, [0xB0, 104, 0]), Some(Event::Release { position: 104 }));
+        // Other channels are not the device's
+        assert_eq!(decode(&mut decoder, [0x91, 11, 127]), None);
+    }
+
+    #[test]
+    fn scroll_finished() {
+        let mut decoder = EventDecoder::new();
+        assert_eq!(decode(&mut decoder, [0xF0, 0x00, 0x20]), None);
+        assert_eq!(decode(&mut decoder, [0x02, 0x18, 0x15]), Some(Event::ScrollFinished));
+        assert_eq!(decode(&mut decoder, [0xF7, 0x00, 0x00]), None);
+
+        // Only right after the start of a Novation sysex
+        assert_eq!(decode(&mut decoder, [0x02, 0x18, 0x15]), None);
+        assert_eq!(decode(&mut decoder, [0xF0, 0x00, 0x20]), None);
+        assert_eq!(decode(&mut decoder, [0x90, 11, 127]),
+                   Some(Event::Press { position: 11, velocity: 127 }));
+        assert_eq!(decode(&mut decoder, [0x02, 0x18, 0x15]), None);
+    }
+}
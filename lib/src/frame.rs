@@ -0,0 +1,241 @@
+//! A picture of every LED on the device.
+//!
+//! Pads are addressed by `(x, y)` with `(0, 0)` the top left pad and `(7, 7)`
+//! the bottom right one, like an image. The round buttons are kept separately:
+//! `top` buttons are numbered 0...7 from the left, and `side` buttons 0...7
+//! from the top.
+
+use color::{LedColor, RGBColor};
+use dither::{dither_image, downscale, Dither};
+
+/// Width and height of the grid of pads
+pub const GRID_SIZE: usize = 8;
+
+/// Position of the pad at `(x, y)`, counting from the top left
+pub fn pad_position(x: usize, y: usize) -> u8 {
+    assert!(x < GRID_SIZE && y < GRID_SIZE, "Bad Pad!");
+    (10 * (GRID_SIZE - y) + x + 1) as u8
+}
+
+/// Position of a top button, counting from the left
+pub fn top_position(index: usize) -> u8 {
+    assert!(index < GRID_SIZE, "Bad Button!");
+    104 + index as u8
+}
+
+/// Position of a side button, counting from the top
+pub fn side_position(index: usize) -> u8 {
+    assert!(index < GRID_SIZE, "Bad Button!");
+    (10 * (GRID_SIZE - index) + 9) as u8
+}
+
+/// Where a position is on the device, the inverse of `pad_position`,
+/// `top_position` and `side_position`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Location {
+    Pad { x: usize, y: usize },
+    Top(usize),
+    Side(usize),
+}
+
+impl Location {
+    /// Locate a position, if it is one of the device's buttons
+    pub fn of(position: u8) -> Option<Location> {
+        let position = position as usize;
+        match position {
+            104..=111 => Some(Location::Top(position - 104)),
+            11..=89 => {
+                let row = position / 10;
+                match position % 10 {
+                    0 => None,
+                    9 => Some(Location::Side(GRID_SIZE - row)),
+                    col => Some(Location::Pad {
+                        x: col - 1,
+                        y: GRID_SIZE - row,
+                    }),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The position of this location
+    pub fn position(&self) -> u8 {
+        match *self {
+            Location::Pad { x, y } => pad_position(x, y),
+            Location::Top(index) => top_position(index),
+            Location::Side(index) => side_position(index),
+        }
+    }
+}
+
+/// The color of every LED on the device
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Frame {
+    pads: [[LedColor; GRID_SIZE]; GRID_SIZE],
+    top: [LedColor; GRID_SIZE],
+    side: [LedColor; GRID_SIZE],
+}
+
+impl Frame {
+    /// A frame with every LED off
+    pub fn new() -> Frame {
+        Frame::default()
+    }
+
+    /// The color of the pad at `(x, y)`
+    pub fn pad(&self, x: usize, y: usize) -> LedColor {
+        self.pads[y][x]
+    }
+
+    /// Set the color of the pad at `(x, y)`
+    pub fn set_pad<C: Into<LedColor>>(&mut self, x: usize, y: usize, color: C) {
+        self.pads[y][x] = color.into();
+    }
+
+    /// The color of a top button
+    pub fn top(&self, index: usize) -> LedColor {
+        self.top[index]
+    }
+
+    /// Set the color of a top button
+    pub fn set_top<C: Into<LedColor>>(&mut self, index: usize, color: C) {
+        self.top[index] = color.into();
+    }
+
+    /// The color of a side button
+    pub fn side(&self, index: usize) -> LedColor {
+        self.side[index]
+    }
+
+    /// Set the color of a side button
+    pub fn set_side<C: Into<LedColor>>(&mut self, index: usize, color: C) {
+        self.side[index] = color.into();
+    }
+
+    /// The color of any LED by location
+    pub fn get(&self, location: Location) -> LedColor {
+        match location {
+            Location::Pad { x, y } => self.pad(x, y),
+            Location::Top(index) => self.top(index),
+            Location::Side(index) => self.side(index),
+        }
+    }
+
+    /// Set the color of any LED by location
+    pub fn set<C: Into<LedColor>>(&mut self, location: Location, color: C) {
+        match location {
+            Location::Pad { x, y } => self.set_pad(x, y, color),
+            Location::Top(index) => self.set_top(index, color),
+            Location::Side(index) => self.set_side(index, color),
+        }
+    }
+
+    /// Set every pad to the same color, leaving the buttons alone
+    pub fn fill_pads<C: Into<LedColor>>(&mut self, color: C) {
+        let color = color.into();
+        for row in self.pads.iter_mut() {
+            for pad in row.iter_mut() {
+                *pad = color;
+            }
+        }
+    }
+
+    /// Every LED position and its color
+    pub fn leds(&self) -> Vec<(u8, LedColor)> {
+        let mut leds = Vec::with_capacity(GRID_SIZE * (GRID_SIZE + 2));
+        for y in 0..GRID_SIZE {
+            for x in 0..GRID_SIZE {
+                leds.push((pad_position(x, y), self.pads[y][x]));
+            }
+        }
+        for i in 0..GRID_SIZE {
+            leds.push((top_position(i), self.top[i]));
+            leds.push((side_position(i), self.side[i]));
+        }
+        leds
+    }
+
+    /// The LED positions and colors which differ from `previous`
+    pub fn diff(&self, previous: &Frame) -> Vec<(u8, LedColor)> {
+        self.leds()
+            .into_iter()
+            .zip(previous.leds())
+            .filter(|&((_, new), (_, old))| new != old)
+            .map(|(led, _)| led)
+            .collect()
+    }
+
+    /// Build a frame from an image of `width` x `height` pixels (row-major,
+    /// top row first), scaling it to fit. With `buttons`, the image covers a
+    /// 9x9 area whose top row lands on the top buttons and right column on the
+    /// side buttons. Without `dither`, pixels become RGB colors; otherwise
+    /// they are matched to palette colors with the given dithering.
+    pub fn from_image(pixels: &[RGBColor],
+                      width: usize,
+                      height: usize,
+                      buttons: bool,
+                      dither: Option<Dither>)
+                      -> Frame {
+        let size = if buttons { GRID_SIZE + 1 } else { GRID_SIZE };
+        let colors: Vec<LedColor> = match dither {
+            Some(dither) => {
+                dither_image(pixels, width, height, size, size, dither)
+                    .into_iter()
+                    .map(LedColor::Palette)
+                    .collect()
+            }
+            None => {
+                downscale(pixels, width, height, size, size)
+                    .into_iter()
+                    .map(LedColor::Rgb)
+                    .collect()
+            }
+        };
+
+        let mut frame = Frame::new();
+        for (i, &color) in colors.iter().enumerate() {
+            let (x, y) = (i % size, i / size);
+            if !buttons {
+                frame.set_pad(x, y, color);
+            } else if y == 0 {
+                if x < GRID_SIZE {
+                    frame.set_top(x, color);
+                }
+            } else if x == GRID_SIZE {
+                frame.set_side(y - 1, color);
+            } else {
+                frame.set_pad(x, y - 1, color);
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_round_trip() {
+        assert_eq!(pad_position(0, 0), 81);
+        assert_eq!(pad_position(7, 7), 18);
+        assert_eq!(side_position(0), 89);
+        assert_eq!(top_position(7), 111);
+        for position in 0..128 {
+            if let Some(location) = Location::of(position) {
+                assert_eq!(location.position(), position);
+            }
+        }
+        assert_eq!(Location::of(99), None);
+    }
+
+    #[test]
+    fn diff_only_reports_changes() {
+        let old = Frame::new();
+        let mut new = Frame::new();
+        new.set_pad(1, 2, 5);
+        assert_eq!(new.diff(&old), vec![(pad_position(1, 2), LedColor::Palette(5))]);
+    }
+}
@@ -2,39 +2,96 @@
 //!
 //! For now, only Launchpad Mark 2 devices are supported.
 
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use pm;
-use color::nearest_palette;
+use calibration::Calibration;
+use color::{nearest_palette, LedColor, RGBColor};
+use devices::guess_ports;
+use event::{Event, EventDecoder};
+use frame::Frame;
+use scroll::transliterate;
 
 pub type Color = u8;
 
+/// Where messages for a device are sent: a PortMidi output port, or
+/// something standing in for the device such as an `Emulator`
+pub trait MidiOutput: Send {
+    fn write_message(&mut self, msg: [u8; 3]) -> pm::Result<()>;
+    fn write_sysex(&mut self, msg: &[u8]) -> pm::Result<()>;
+}
+
+/// Where messages from a device arrive
+pub trait MidiInput: Send {
+    /// Read up to `max` pending messages, if there are any
+    fn read_n(&self, max: usize) -> pm::Result<Option<Vec<pm::MidiEvent>>>;
+}
+
+impl MidiOutput for pm::OutputPort {
+    fn write_message(&mut self, msg: [u8; 3]) -> pm::Result<()> {
+        pm::OutputPort::write_message(self, msg)
+    }
+
+    fn write_sysex(&mut self, msg: &[u8]) -> pm::Result<()> {
+        pm::OutputPort::write_sysex(self, 0, msg)
+    }
+}
+
+impl MidiInput for pm::InputPort {
+    fn read_n(&self, max: usize) -> pm::Result<Option<Vec<pm::MidiEvent>>> {
+        self.poll()?;
+        pm::InputPort::read_n(self, max)
+    }
+}
+
 /// A Launchpad Mark 2 Device. This library requires the PortMidi device
 /// used to create the launchpad to have the same lifetime. If we create the
-/// PortMidi device ourselves, hold it. Otherwise, trust the implementer to
-/// not destroy it (or further calls will fail (sometimes silently?))
+/// PortMidi device ourselves, hold it, sharing it with any `Clock` which
+/// outlives us. Otherwise, trust the implementer to not destroy it (or
+/// further calls will fail (sometimes silently?))
 pub struct LaunchpadMk2 {
-    input_port: pm::InputPort,
-    output_port: pm::OutputPort,
-    midi: Option<pm::PortMidi>,
+    input_port: Box<dyn MidiInput>,
+    output_port: Arc<Mutex<Box<dyn MidiOutput>>>,
+    /// Dropped after the ports
+    midi: Option<Arc<pm::PortMidi>>,
+    calibration: Calibration,
+    decoder: EventDecoder,
+    pending: VecDeque<Event>,
 }
 
-/// A single button/led
+/// A single button/led, set to a palette `Color` or any `LedColor`
 #[derive(Debug)]
-pub struct ColorLed {
-    pub color: Color,
+pub struct ColorLed<C = Color> {
+    pub color: C,
     pub position: u8,
 }
 
+/// A single button/led set to an RGB color
 #[derive(Debug)]
-/// A single column (0...8)
-pub struct ColorColumn {
-    pub color: Color,
+pub struct RgbLed {
+    pub color: RGBColor,
+    pub position: u8,
+}
+
+/// A single column: 0...7 from the left like `Frame`, with its top button,
+/// or 8 for the side buttons
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ColorColumn<C = Color> {
+    pub color: C,
     pub column: u8,
 }
 
-/// A single row (0...8)
+/// A single row (0...8), counted from the bottom like the device does. Row 8
+/// is the top row of buttons.
 #[derive(Debug)]
-pub struct ColorRow {
-    pub color: Color,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ColorRow<C = Color> {
+    pub color: C,
     pub row: u8,
 }
 
@@ -52,7 +109,7 @@ impl LaunchpadMk2 {
     pub fn guess() -> LaunchpadMk2 {
         let midi = pm::PortMidi::new().expect("Failed to open PortMidi Instance!");
         let mut retval = Self::guess_from(&midi);
-        retval.midi = Some(midi);
+        retval.midi = Some(Arc::new(midi));
         retval
     }
 
@@ -61,27 +118,7 @@ impl LaunchpadMk2 {
     /// PortMidi.
     pub fn guess_from(midi: &pm::PortMidi) -> LaunchpadMk2 {
         let devs = midi.devices().expect("Failed to get Midi Device!");
-
-        let mut input: Option<i32> = None;
-        let mut output: Option<i32> = None;
-
-        for d in devs {
-            if !d.name().contains("Launchpad MK2") {
-                continue;
-            }
-
-            if input.is_none() && d.is_input() {
-                input = Some(d.id() as i32);
-            }
-
-            if output.is_none() && d.is_output() {
-                output = Some(d.id() as i32);
-            }
-
-            if input.is_some() && output.is_some() {
-                break;
-            }
-        }
+        let (input, output) = guess_ports(&devs);
 
         let input_port = input.expect("No Launchpad Mk2 Input Found!");
         let output_port = output.expect("No Launchpad Mk2 Output Found!");
@@ -96,128 +133,323 @@ impl LaunchpadMk2 {
         let output = midi.output_port(output_device, 1024)
             .expect("Failed to open port");
 
+        LaunchpadMk2::from_ports(input, output)
+    }
+
+    /// Drive a device through the given ports. They can be PortMidi ports
+    /// opened by the caller, or anything else speaking the device's
+    /// messages, such as an `Emulator`.
+    pub fn from_ports<I, O>(input: I, output: O) -> LaunchpadMk2
+        where I: MidiInput + 'static,
+              O: MidiOutput + 'static
+    {
         LaunchpadMk2 {
-            input_port: input,
-            output_port: output,
+            input_port: Box::new(input),
+            output_port: Arc::new(Mutex::new(Box::new(output))),
             midi: None,
+            calibration: Calibration::default(),
+            decoder: EventDecoder::new(),
+            pending: VecDeque::new(),
         }
     }
 
-    /// Set all LEDs to the same color
-    pub fn light_all(&mut self, color: Color) {
-        assert_color(color);
-        // F0h 00h 20h 29h 02h 18h 0Eh <Colour> F7h
-        // Message cannot be repeated.
-        self.output_port
-            .write_sysex(0, &[0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0E, color, 0xF7])
-            .expect("Fail");
+    /// The calibration used to convert `RGBColor`s to device values
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// Replace the calibration used to convert `RGBColor`s to device values,
+    /// e.g. with one loaded by `Calibration::load`
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Set all LEDs to the same color. RGB colors not in the palette are set
+    /// LED by LED.
+    pub fn light_all<C: Into<LedColor>>(&mut self, color: C) {
+        let color = color.into();
+        match color.palette() {
+            Some(color) => {
+                assert_color(color);
+                // F0h 00h 20h 29h 02h 18h 0Eh <Colour> F7h
+                // Message cannot be repeated.
+                self.write_sysex(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0E, color, 0xF7])
+                    .expect("Fail");
+            }
+            None => {
+                let leds = (0..9).flat_map(row_positions).map(|p| (p, color)).collect::<Vec<_>>();
+                for chunk in leds.chunks(80) {
+                    self.set_leds(chunk);
+                }
+            }
+        }
+    }
+
+    /// Set a single LED. Palette colors, and RGB colors exactly matching a
+    /// palette entry, use the 3 byte message, which is faster than
+    /// `light_led`. Other RGB colors use `light_rgb`.
+    pub fn light_single<C: Into<LedColor> + Copy>(&mut self, led: &ColorLed<C>) {
+        // ch1
+        // (0x90, <btn>, <color>)
+        assert_position(led.position);
+        let color = led.color.into();
+        match color.palette() {
+            Some(palette) => {
+                assert_color(palette);
+                self.write_message([0x90, led.position, palette]).expect("Fail");
+            }
+            None => {
+                self.light_rgb(&RgbLed {
+                    position: led.position,
+                    color: color.rgb(),
+                })
+            }
+        }
     }
 
     /// Set a single LED to flash. Uses a smaller header than `flash_led` or
-    /// `flash_leds` with a single item
-    pub fn flash_single(&mut self, led: &ColorLed) {
+    /// `flash_leds` with a single item. Flashing only supports palette colors,
+    /// so RGB colors use their nearest palette color.
+    pub fn flash_single<C: Into<LedColor> + Copy>(&mut self, led: &ColorLed<C>) {
         // ch2
         // (0x91, <btn>, <color>)
+        let color = led.color.into().nearest_palette();
         assert_position(led.position);
-        assert_color(led.color);
-        self.output_port.write_message([0x91, led.position, led.color]).expect("Fail");
+        assert_color(color);
+        self.write_message([0x91, led.position, color]).expect("Fail");
     }
 
     /// Set a single LED to pulse. Uses a smaller header than `pulse_led` or
-    /// `pulse_leds` with a single item
-    pub fn pulse_single(&mut self, led: &ColorLed) {
+    /// `pulse_leds` with a single item. Pulsing only supports palette colors,
+    /// so RGB colors use their nearest palette color.
+    pub fn pulse_single<C: Into<LedColor> + Copy>(&mut self, led: &ColorLed<C>) {
         // ch3
         // (0x92, <btn>, <color>)
-
-        self.output_port.write_message([0x92, led.position, led.color]).expect("Fail");
+        let color = led.color.into().nearest_palette();
+        assert_position(led.position);
+        assert_color(color);
+        self.write_message([0x92, led.position, color]).expect("Fail");
     }
 
-    /// Set a single LED to a palette color. Use `light_single` instead, its faster.
-    pub fn light_led(&mut self, led: &ColorLed) {
+    /// Set a single LED. Use `light_single` instead, its faster.
+    pub fn light_led<C: Into<LedColor> + Copy>(&mut self, led: &ColorLed<C>) {
         // F0h 00h 20h 29h 02h 18h 0Ah <LED> <Colour> F7h
         // Message can be repeated up to 80 times.
         self.light_leds(&[led])
     }
 
     /// Set LEDs to a certain color. Up to 80 LEDs can be set uniquely at once.
-    pub fn light_leds(&mut self, leds: &[&ColorLed]) {
+    /// RGB colors not in the palette are sent together as one `light_rgbs`
+    /// message.
+    pub fn light_leds<C: Into<LedColor> + Copy>(&mut self, leds: &[&ColorLed<C>]) {
         assert!(leds.len() <= 80);
+        let mut rgb = Vec::new();
         for led in leds {
             assert_position(led.position);
-            assert_color(led.color);
-            self.output_port
-                .write_sysex(0,
-                             &[0xF0,
-                               0x00,
-                               0x20,
-                               0x29,
-                               0x02,
-                               0x18,
-                               0x0A,
-                               led.position,
-                               led.color,
-                               0xF7])
+            let color = led.color.into();
+            let palette = match color.palette() {
+                Some(palette) => palette,
+                None => {
+                    rgb.push(RgbLed {
+                        position: led.position,
+                        color: color.rgb(),
+                    });
+                    continue;
+                }
+            };
+            assert_color(palette);
+            self.write_sysex(&[0xF0,
+                                 0x00,
+                                 0x20,
+                                 0x29,
+                                 0x02,
+                                 0x18,
+                                 0x0A,
+                                 led.position,
+                                 palette,
+                                 0xF7])
                 .expect("Fail");
         }
+        if !rgb.is_empty() {
+            self.light_rgbs(&rgb.iter().collect::<Vec<_>>());
+        }
+    }
+
+    /// Set a single LED to an RGB color, converted with the device calibration.
+    pub fn light_rgb(&mut self, led: &RgbLed) {
+        self.light_rgbs(&[led])
+    }
+
+    /// Set LEDs to RGB colors, converted with the device calibration. Up to 80
+    /// LEDs can be set uniquely at once.
+    pub fn light_rgbs(&mut self, leds: &[&RgbLed]) {
+        // F0h 00h 20h 29h 02h 18h 0Bh <LED>, <Red> <Green> <Blue> F7h
+        // Message can be repeated up to 80 times.
+        assert!(leds.len() <= 80);
+        let mut msg: Vec<u8> = vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0B];
+        for led in leds {
+            assert_position(led.position);
+            let [red, green, blue] = self.calibration.apply(&led.color);
+            msg.extend_from_slice(&[led.position, red, green, blue]);
+        }
+        msg.push(0xF7);
+
+        self.write_sysex(&msg).expect("Fail");
     }
 
     /// Light a column of LEDs to the same color.
-    pub fn light_column(&mut self, col: &ColorColumn) {
+    pub fn light_column<C: Into<LedColor> + Copy>(&mut self, col: &ColorColumn<C>) {
         // F0h 00h 20h 29h 02h 18h 0Ch <Column> <Colour> F7h
         // Message can be repeated up to 9 times.
         self.light_columns(&[col])
     }
 
     /// Light columns of LEDs to the same color. Each column may be set to a
-    /// unique color. Up to 9 columns may be set at once.
-    pub fn light_columns(&mut self, cols: &[&ColorColumn]) {
+    /// unique color. Up to 9 columns may be set at once. RGB colors not in
+    /// the palette are set LED by LED.
+    pub fn light_columns<C: Into<LedColor> + Copy>(&mut self, cols: &[&ColorColumn<C>]) {
         assert!(cols.len() <= 9);
         for col in cols {
             assert_column(col.column);
-            assert_color(col.color);
-            self.output_port
-                .write_sysex(0,
-                             &[0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0C, col.column, col.color,
-                               0xF7])
-                .expect("Fail");
+            let color = col.color.into();
+            match color.palette() {
+                Some(palette) => {
+                    assert_color(palette);
+                    self.write_sysex(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0C, col.column, palette,
+                                         0xF7])
+                        .expect("Fail");
+                }
+                None => {
+                    let leds = column_positions(col.column).map(|p| (p, color)).collect::<Vec<_>>();
+                    self.set_leds(&leds);
+                }
+            }
         }
     }
 
     /// Light a row of LEDs to the same color.
-    pub fn light_row(&mut self, row: &ColorRow) {
+    pub fn light_row<C: Into<LedColor> + Copy>(&mut self, row: &ColorRow<C>) {
         // F0h 00h 20h 29h 02h 18h 0Dh <Row> <Colour> F7h
         // Message can be repeated up to 9 times.
         self.light_rows(&[row])
     }
 
     /// Light rows of LEDs to the same color. Each row may be set to a
-    /// unique color. Up to 9 rows may be set at once.
-    pub fn light_rows(&mut self, rows: &[&ColorRow]) {
+    /// unique color. Up to 9 rows may be set at once. RGB colors not in the
+    /// palette are set LED by LED.
+    pub fn light_rows<C: Into<LedColor> + Copy>(&mut self, rows: &[&ColorRow<C>]) {
         assert!(rows.len() <= 9);
         for row in rows {
             assert_row(row.row);
-            assert_color(row.color);
-            self.output_port
-                .write_sysex(0,
-                             &[0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0D, row.row, row.color, 0xF7])
-                .expect("Fail");
+            let color = row.color.into();
+            match color.palette() {
+                Some(palette) => {
+                    assert_color(palette);
+                    self.write_sysex(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0D, row.row, palette,
+                                         0xF7])
+                        .expect("Fail");
+                }
+                None => {
+                    let leds = row_positions(row.row).map(|p| (p, color)).collect::<Vec<_>>();
+                    self.set_leds(&leds);
+                }
+            }
+        }
+    }
+
+    /// Set a single LED, like `light_single`
+    pub fn set_led<C: Into<LedColor>>(&mut self, position: u8, color: C) {
+        self.light_single(&ColorLed {
+            position,
+            color: color.into(),
+        })
+    }
+
+    /// Set LEDs, like `light_leds`. Up to 80 LEDs can be set at once.
+    pub fn set_leds(&mut self, leds: &[(u8, LedColor)]) {
+        let leds = leds.iter().map(|&(position, color)| ColorLed { position, color }).collect::<Vec<_>>();
+        self.light_leds(&leds.iter().collect::<Vec<_>>());
+    }
+
+    /// Light a row, like `light_row`, but counting rows 0...7 from the top
+    /// like `Frame`. Row 8 is still the top row of buttons.
+    pub fn set_row<C: Into<LedColor>>(&mut self, row: u8, color: C) {
+        assert_row(row);
+        self.light_row(&ColorRow {
+            row: if row == 8 { 8 } else { 7 - row },
+            color: color.into(),
+        })
+    }
+
+    /// Light a column, like `light_column`
+    pub fn set_column<C: Into<LedColor>>(&mut self, column: u8, color: C) {
+        self.light_column(&ColorColumn {
+            column,
+            color: color.into(),
+        })
+    }
+
+    /// Set all LEDs, like `light_all`
+    pub fn set_all<C: Into<LedColor>>(&mut self, color: C) {
+        self.light_all(color)
+    }
+
+    /// Set a single LED to flash, like `flash_single`
+    pub fn set_flash<C: Into<LedColor>>(&mut self, position: u8, color: C) {
+        self.flash_single(&ColorLed {
+            position,
+            color: color.into(),
+        })
+    }
+
+    /// Set a single LED to pulse, like `pulse_single`
+    pub fn set_pulse<C: Into<LedColor>>(&mut self, position: u8, color: C) {
+        self.pulse_single(&ColorLed {
+            position,
+            color: color.into(),
+        })
+    }
+
+    /// Light every LED to match a frame.
+    pub fn draw_frame(&mut self, frame: &Frame) {
+        for chunk in frame.leds().chunks(80) {
+            self.set_leds(chunk);
+        }
+    }
+
+    /// Light only the LEDs which differ between `frame` and the `previous`
+    /// frame drawn.
+    pub fn draw_frame_diff(&mut self, frame: &Frame, previous: &Frame) {
+        for chunk in frame.diff(previous).chunks(80) {
+            self.set_leds(chunk);
         }
     }
 
     /// Begin scrolling a message. The screen will be blanked, and the letters
     /// will be the same color. If the message is set to loop, it can be cancelled
-    /// by sending an empty `scroll_text` command. String should only contain ASCII
-    /// characters, or the byte value of 1-7 to set the speed (`\u{01}` to `\u{07}`)
-    pub fn scroll_text(&mut self, color: Color, doloop: bool, text: &str) {
+    /// by sending an empty `scroll_text` command. String should contain ASCII
+    /// characters, or the byte value of 1-7 to set the speed (`\u{01}` to `\u{07}`).
+    /// Other characters are transliterated like `ScrollText::text` does; use
+    /// `ScrollText::ascii` to reject them instead. A non-looping message
+    /// sends `Event::ScrollFinished` when done, see `wait_scroll_finished`.
+    /// Scrolling only supports palette colors, so RGB colors use their nearest
+    /// palette color.
+    pub fn scroll_text<C: Into<LedColor>>(&mut self, color: C, doloop: bool, text: &str) {
         // 14H <Color> <loop> <text...> F7h
         // Message cannot be repeated.
+        let color = color.into().nearest_palette();
         assert_color(color);
         let mut msg: Vec<u8> =
             vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x14, color, if doloop { 0x01 } else { 0x00 }];
-        msg.extend_from_slice(text.as_bytes());
+        for c in text.chars() {
+            match c {
+                ' '..='~' | '\u{01}'..='\u{07}' => msg.push(c as u8),
+                _ => msg.extend_from_slice(transliterate(c).as_bytes()),
+            }
+        }
         msg.push(0xF7);
 
-        self.output_port.write_sysex(0, &msg).expect("Fail");
+        self.write_sysex(&msg).expect("Fail");
     }
 
     /// Experimental. Try to set an LED by the color value in a "fast" way by
@@ -233,13 +465,134 @@ impl LaunchpadMk2 {
 
     /// Retrieve pending MidiEvents
     pub fn poll(&self) -> Option<Vec<pm::MidiEvent>> {
-        self.input_port.poll().expect("Closed Stream");
         self.input_port.read_n(1024).expect("Failed to read")
     }
+
+    /// Retrieve pending events, decoded. Don't mix with `poll`, which would
+    /// take the raw messages from under this.
+    pub fn poll_events(&mut self) -> Vec<Event> {
+        if let Some(raw) = self.poll() {
+            for event in raw {
+                if let Some(event) = self.decoder.decode(&event.message) {
+                    self.pending.push_back(event);
+                }
+            }
+        }
+        self.pending.drain(..).collect()
+    }
+
+    /// Block until a non-looping `scroll_text` message has finished, or until
+    /// `timeout` has passed. Returns whether the message finished. Other
+    /// events arriving meanwhile are kept for the next `poll_events`.
+    pub fn wait_scroll_finished(&mut self, timeout: Option<Duration>) -> bool {
+        let start = Instant::now();
+        let mut seen = 0;
+        loop {
+            if let Some(raw) = self.poll() {
+                for event in raw {
+                    if let Some(event) = self.decoder.decode(&event.message) {
+                        self.pending.push_back(event);
+                    }
+                }
+            }
+            // Only consume the first ScrollFinished, leaving everything else
+            if let Some(i) = self.pending.iter().skip(seen).position(|e| *e == Event::ScrollFinished) {
+                self.pending.remove(seen + i);
+                return true;
+            }
+            seen = self.pending.len();
+
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                return false;
+            }
+            // there is no blocking receive method in PortMidi
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Send one MIDI clock tick. The device times flashing and pulsing from
+    /// the clock it receives, 24 ticks per beat, and falls back to 120 BPM
+    /// without one. See `Clock` to send ticks in the background.
+    pub fn send_clock(&self) {
+        self.write_message([0xF8, 0, 0]).expect("Fail");
+    }
+
+    /// Replace the ports with wrappers around them, e.g. to watch the
+    /// messages going through
+    pub(crate) fn wrap_ports<F, G>(&mut self, wrap_input: F, wrap_output: G)
+        where F: FnOnce(Box<dyn MidiInput>) -> Box<dyn MidiInput>,
+              G: FnOnce(Box<dyn MidiOutput>) -> Box<dyn MidiOutput>
+    {
+        let input = mem::replace(&mut self.input_port, Box::new(Unplugged));
+        self.input_port = wrap_input(input);
+
+        let mut output_port = self.output_port.lock().expect("Output lock poisoned");
+        let output = mem::replace(&mut *output_port, Box::new(Unplugged));
+        *output_port = wrap_output(output);
+    }
+
+    /// The output port, shared with background senders such as `Clock`
+    pub(crate) fn shared_output(&self) -> Arc<Mutex<Box<dyn MidiOutput>>> {
+        self.output_port.clone()
+    }
+
+    /// The PortMidi context, if we created it, for background senders to
+    /// keep alive as long as they use the output port
+    pub(crate) fn shared_midi(&self) -> Option<Arc<pm::PortMidi>> {
+        self.midi.clone()
+    }
+
+    fn write_message(&self, msg: [u8; 3]) -> pm::Result<()> {
+        self.output_port.lock().expect("Output lock poisoned").write_message(msg)
+    }
+
+    fn write_sysex(&self, msg: &[u8]) -> pm::Result<()> {
+        self.output_port.lock().expect("Output lock poisoned").write_sysex(msg)
+    }
+}
+
+/// Stands in for a port while it is being replaced
+struct Unplugged;
+
+impl MidiOutput for Unplugged {
+    fn write_message(&mut self, _: [u8; 3]) -> pm::Result<()> {
+        Ok(())
+    }
+
+    fn write_sysex(&mut self, _: &[u8]) -> pm::Result<()> {
+        Ok(())
+    }
+}
+
+impl MidiInput for Unplugged {
+    fn read_n(&self, _: usize) -> pm::Result<Option<Vec<pm::MidiEvent>>> {
+        Ok(None)
+    }
+}
+
+/// Positions of the LEDs in a row as the device numbers them: 0...7 from the
+/// bottom, and 8 for the top row of buttons.
+pub(crate) fn row_positions(row: u8) -> Box<dyn Iterator<Item = u8>> {
+    if row == 8 {
+        Box::new(104..112)
+    } else {
+        let first = 10 * (row + 1) + 1;
+        Box::new(first..first + 9)
+    }
+}
+
+/// Positions of the LEDs in a column (0...8). Column 8 is the right column
+/// of buttons, which has no LED in the top row.
+pub(crate) fn column_positions(column: u8) -> Box<dyn Iterator<Item = u8>> {
+    if column == 8 {
+        Box::new((1..9).map(|row| 10 * row + 9))
+    } else {
+        Box::new((1..9).map(move |row| 10 * row + column + 1).chain(Some(104 + column)))
+    }
 }
 
 /// Make sure the position is valid
-fn assert_position(pos: u8) {
+pub(crate) fn assert_position(pos: u8) {
     // Probably just make a Result
     if !match pos {
         11...19 => true,
@@ -278,6 +631,83 @@ fn assert_row(row: u8) {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color::COLOR_PALETTE;
+    use emulator::Emulator;
+    use palette;
+    use session::Recorder;
+
+    /// The messages a lighting call sends
+    fn sent<F: FnOnce(&mut LaunchpadMk2)>(light: F) -> Vec<Vec<u8>> {
+        let (_emulator, mut lpad) = Emulator::new();
+        let recorder = Recorder::attach(&mut lpad);
+        light(&mut lpad);
+        recorder.session().messages.into_iter().map(|m| m.bytes).collect()
+    }
+
+    const HEADER: [u8; 6] = [0xF0, 0x00, 0x20, 0x29, 0x02, 0x18];
+    const OFF_PALETTE: RGBColor = RGBColor::new(1, 2, 3);
+
+    #[test]
+    fn palette_or_rgb() {
+        // Palette colors, and RGB colors in the palette, use the note message
+        assert_eq!(sent(|l| l.set_led(11, palette::RED)), vec![vec![0x90, 11, palette::RED]]);
+        assert_eq!(sent(|l| l.light_single(&ColorLed { position: 11, color: COLOR_PALETTE[5] })),
+                   vec![vec![0x90, 11, 5]]);
+        assert_eq!(sent(|l| l.set_led(11, LedColor::Off)), vec![vec![0x90, 11, 0]]);
+
+        let rgb = sent(|l| l.set_led(11, OFF_PALETTE));
+        assert_eq!(rgb.len(), 1);
+        assert_eq!(rgb[0][..8], [0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0B, 11]);
+
+        let mixed = sent(|l| l.set_leds(&[(11, LedColor::Palette(palette::BLUE)), (12, OFF_PALETTE.into())]));
+        assert_eq!(mixed.len(), 2);
+        assert_eq!(mixed[0], [&HEADER[..], &[0x0A, 11, palette::BLUE, 0xF7]].concat());
+        assert_eq!(mixed[1][6..8], [0x0B, 12]);
+
+        // Flashing, pulsing and scrolling fall back to the nearest palette color
+        assert_eq!(sent(|l| l.set_flash(11, RGBColor::new(250, 10, 0))), vec![vec![0x91, 11, 5]]);
+        assert_eq!(sent(|l| l.scroll_text(RGBColor::new(250, 10, 0), false, ""))[0][7], 5);
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        // light_row counts rows from the bottom like the device, set_row from
+        // the top like Frame
+        assert_eq!(sent(|l| l.light_row(&ColorRow { row: 0, color: palette::BLUE })),
+                   vec![[&HEADER[..], &[0x0D, 0, palette::BLUE, 0xF7]].concat()]);
+        assert_eq!(sent(|l| l.set_row(0, palette::BLUE))[0][7], 7);
+        assert_eq!(sent(|l| l.set_row(8, palette::BLUE))[0][7], 8);
+        assert_eq!(sent(|l| l.set_column(8, palette::BLUE))[0][7], 8);
+
+        // RGB rows and columns are set LED by LED
+        let row = sent(|l| l.set_row(0, OFF_PALETTE));
+        let positions: Vec<u8> = row[0][7..row[0].len() - 1].chunks(4).map(|led| led[0]).collect();
+        assert_eq!(positions, (81..90).collect::<Vec<_>>());
+        let column = sent(|l| l.set_column(0, OFF_PALETTE));
+        assert_eq!(column[0][7..column[0].len() - 1].len(), 9 * 4);
+
+        // An RGB color for everything sets all 80 LEDs in one message
+        let all = sent(|l| l.light_all(OFF_PALETTE));
+        assert_eq!((all.len(), all[0].len()), (1, 7 + 80 * 4 + 1));
+        assert_eq!(sent(|l| l.light_all(palette::RED)).len(), 1);
+    }
+
+    #[test]
+    fn scroll_transliterates() {
+        let msg = sent(|l| l.scroll_text(palette::RED, false, "\u{02}Café…"));
+        assert_eq!(msg[0][9..], *b"\x02Cafe...\xF7");
+    }
+
+    #[test]
+    #[should_panic(expected = "Bad Color!")]
+    fn palette_out_of_range() {
+        sent(|l| l.set_led(11, LedColor::Palette(128)));
+    }
+}
+
 //////////////////////////////////////////////////////////////////
 // TODO ITEMS
 //////////////////////////////////////////////////////////////////
@@ -332,11 +762,6 @@ fn assert_row(row: u8) {
 
 // }
 
-// pub fn light_rgb(light: u8, red: u8, green: u8, blue: u8) {
-//     // F0h 00h 20h 29h 02h 18h 0Bh <LED>, <Red> <Green> <Blue> F7h
-//     // Message can be repeated up to 80 times.
-// }
-
 // pub fn start_vol_fader() {
 
 // }
@@ -1,10 +1,53 @@
 extern crate portmidi as pm;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+mod animation;
+mod calibration;
+mod clock;
 mod color;
+mod devices;
+mod dither;
+mod draw;
+mod emulator;
+mod event;
+mod frame;
 mod launchpad;
+mod mapping;
+mod midifile;
+mod pages;
+mod scroll;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sequencer;
+mod session;
+mod text;
+mod widget;
+
+pub mod palette;
 
 pub use launchpad::*;
+pub use animation::*;
+pub use calibration::*;
+pub use clock::*;
 pub use color::*;
+pub use devices::*;
+pub use dither::*;
+pub use draw::*;
+pub use emulator::*;
+pub use event::*;
+pub use frame::*;
+pub use mapping::*;
+pub use midifile::*;
+pub use pages::*;
+pub use scroll::*;
+pub use sequencer::*;
+pub use session::*;
+pub use text::*;
+pub use widget::*;
 
 #[cfg(test)]
 mod tests {
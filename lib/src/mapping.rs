@@ -0,0 +1,330 @@
+//! Remapping of buttons to MIDI messages for other devices.
+//!
+//! A `Mapping` turns presses and releases into notes, control changes or
+//! program changes, keeps track of which buttons are on, and draws them.
+//! Like the `Sequencer`, it only works out which messages to send; the caller
+//! sends them to whichever output the synth listens on.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use color::parse_color;
+use event::Event;
+use frame::{Frame, Location, GRID_SIZE};
+use palette;
+use widget::Colors;
+
+/// What a button sends
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MidiTarget {
+    /// Note on when switched on, note off when switched off
+    Note(u8),
+    /// The control at its value when switched on, and 0 when switched off
+    ControlChange(u8),
+    /// A program change on press. Buttons with program changes on the same
+    /// channel act as a radio group, the last one pressed staying lit.
+    ProgramChange(u8),
+}
+
+/// When a button switches on and off
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Mode {
+    /// On while held down
+    Momentary,
+    /// On or off with each press
+    Toggle,
+}
+
+/// How one button is mapped
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ButtonMapping {
+    pub location: Location,
+    pub target: MidiTarget,
+    /// MIDI channel, 0...15
+    pub channel: u8,
+    pub mode: Mode,
+    /// Note velocity or control value. `None` passes on how hard the button
+    /// was pressed.
+    pub value: Option<u8>,
+    pub colors: Colors,
+}
+
+/// Buttons mapped to MIDI messages
+#[derive(Debug, Clone, Default)]
+pub struct Mapping {
+    buttons: Vec<ButtonMapping>,
+    on: Vec<bool>,
+}
+
+impl Mapping {
+    /// A mapping of the given buttons. Where a location is mapped more than
+    /// once, the last mapping wins.
+    pub fn new(buttons: Vec<ButtonMapping>) -> Mapping {
+        let mut kept: Vec<ButtonMapping> = Vec::new();
+        for button in buttons {
+            kept.retain(|b| b.location != button.location);
+            kept.push(button);
+        }
+        Mapping {
+            on: vec![false; kept.len()],
+            buttons: kept,
+        }
+    }
+
+    /// Parse a mapping. Each line maps one button: its location (`pad x y`
+    /// with `pad 0 0` at the top left, `top i` or `side i` with 0 at the
+    /// top left), then what it sends (`note n`, `cc n` or `program n`), then
+    /// any of these options:
+    ///
+    /// * `channel n`, 1...16, default 1
+    /// * `momentary` (the default) or `toggle`
+    /// * `value n`, the note velocity or control value, by default how hard
+    ///   the button was pressed (127 for the top and side buttons)
+    /// * `on color` and `off color`, palette names like `RED_DIM` or
+    ///   `#rrggbb`, default `GREEN` and `OFF`
+    ///
+    /// `#` starts a comment, except at the start of a color after `on` or
+    /// `off`.
+    ///
+    /// ```text
+    /// pad 0 7  note 36  channel 10  on ORANGE off ORANGE_DARK
+    /// side 0   cc 64    toggle  value 127  on #40ff40
+    /// top 0    program 0
+    /// top 1    program 1
+    /// ```
+    pub fn parse(config: &str) -> Result<Mapping, MappingError> {
+        let mut buttons = Vec::new();
+
+        for (i, line) in config.lines().enumerate() {
+            let words = words(line);
+            if words.is_empty() {
+                continue;
+            }
+            let bad = |message: String| MappingError::Parse {
+                line: i + 1,
+                message,
+            };
+            let number = |word: Option<&str>, what: &str, range: RangeInclusive<u8>| {
+                let word = word.ok_or_else(|| bad(format!("expected {}", what)))?;
+                word.parse::<u8>()
+                    .ok()
+                    .filter(|n| range.contains(n))
+                    .ok_or_else(|| bad(format!("bad {}: {}", what, word)))
+            };
+            let color = |word: Option<&str>| {
+                let word = word.ok_or_else(|| bad("expected a color".to_string()))?;
+                parse_color(word).ok_or_else(|| bad(format!("bad color: {}", word)))
+            };
+
+            let mut words = words.into_iter();
+            let grid = 0..=GRID_SIZE as u8 - 1;
+            let location = match words.next() {
+                Some("pad") => {
+                    let x = number(words.next(), "column", grid.clone())? as usize;
+                    let y = number(words.next(), "row", grid)? as usize;
+                    Location::Pad { x, y }
+                }
+                Some("top") => Location::Top(number(words.next(), "button", grid)? as usize),
+                Some("side") => Location::Side(number(words.next(), "button", grid)? as usize),
+                _ => return Err(bad("expected `pad`, `top` or `side`".to_string())),
+            };
+            let target = match words.next() {
+                Some("note") => MidiTarget::Note(number(words.next(), "note", 0..=127)?),
+                Some("cc") => MidiTarget::ControlChange(number(words.next(), "control", 0..=127)?),
+                Some("program") => MidiTarget::ProgramChange(number(words.next(), "program", 0..=127)?),
+                _ => return Err(bad("expected `note`, `cc` or `program`".to_string())),
+            };
+
+            let mut button = ButtonMapping {
+                location,
+                target,
+                channel: 0,
+                mode: Mode::Momentary,
+                value: None,
+                colors: Colors::new(palette::GREEN, palette::OFF),
+            };
+            while let Some(option) = words.next() {
+                match option {
+                    "channel" => button.channel = number(words.next(), "channel", 1..=16)? - 1,
+                    "momentary" => button.mode = Mode::Momentary,
+                    "toggle" => button.mode = Mode::Toggle,
+                    "value" => button.value = Some(number(words.next(), "value", 0..=127)?),
+                    "on" => button.colors.on = color(words.next())?,
+                    "off" => button.colors.off = color(words.next())?,
+                    _ => return Err(bad(format!("unknown option: {}", option))),
+                }
+            }
+            buttons.push(button);
+        }
+
+        Ok(Mapping::new(buttons))
+    }
+
+    /// Load a mapping from a file. See `parse` for the format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Mapping, MappingError> {
+        let config = fs::read_to_string(path).map_err(MappingError::Io)?;
+        Mapping::parse(&config)
+    }
+
+    /// The mapped buttons
+    pub fn buttons(&self) -> &[ButtonMapping] {
+        &self.buttons
+    }
+
+    /// Whether the button at a location is mapped and on
+    pub fn is_on(&self, location: Location) -> bool {
+        self.index(location).is_some_and(|i| self.on[i])
+    }
+
+    fn index(&self, location: Location) -> Option<usize> {
+        self.buttons.iter().position(|b| b.location == location)
+    }
+
+    /// React to a press or release, returning the MIDI messages to send
+    pub fn handle(&mut self, event: &Event) -> Vec<[u8; 3]> {
+        let (position, velocity, pressed) = match *event {
+            Event::Press { position, velocity } => (position, velocity, true),
+            Event::Release { position } => (position, 0, false),
+            _ => return Vec::new(),
+        };
+        let index = match Location::of(position).and_then(|l| self.index(l)) {
+            Some(index) => index,
+            None => return Vec::new(),
+        };
+        let button = self.buttons[index];
+
+        if let MidiTarget::ProgramChange(program) = button.target {
+            if !pressed {
+                return Vec::new();
+            }
+            for (i, other) in self.buttons.iter().enumerate() {
+                if other.channel == button.channel {
+                    if let MidiTarget::ProgramChange(_) = other.target {
+                        self.on[i] = i == index;
+                    }
+                }
+            }
+            return vec![[0xC0 | button.channel, program, 0]];
+        }
+
+        let on = match button.mode {
+            Mode::Momentary => pressed,
+            Mode::Toggle if pressed => !self.on[index],
+            Mode::Toggle => return Vec::new(),
+        };
+        self.on[index] = on;
+
+        let value = if on { button.value.unwrap_or(velocity) } else { 0 };
+        match button.target {
+            MidiTarget::Note(note) if on => vec![[0x90 | button.channel, note, value.max(1)]],
+            MidiTarget::Note(note) => vec![[0x80 | button.channel, note, 0]],
+            MidiTarget::ControlChange(control) => vec![[0xB0 | button.channel, control, value]],
+            MidiTarget::ProgramChange(_) => unreachable!(),
+        }
+    }
+
+    /// Switch everything off, returning the note offs and zeroed controls to
+    /// send for buttons which were on
+    pub fn release_all(&mut self) -> Vec<[u8; 3]> {
+        let mut msgs = Vec::new();
+        for (button, on) in self.buttons.iter().zip(self.on.iter_mut()) {
+            if !*on {
+                continue;
+            }
+            *on = false;
+            match button.target {
+                MidiTarget::Note(note) => msgs.push([0x80 | button.channel, note, 0]),
+                MidiTarget::ControlChange(control) => msgs.push([0xB0 | button.channel, control, 0]),
+                MidiTarget::ProgramChange(_) => {}
+            }
+        }
+        msgs
+    }
+
+    /// Draw the mapped buttons, on or off
+    pub fn draw(&self, frame: &mut Frame) {
+        for (button, &on) in self.buttons.iter().zip(self.on.iter()) {
+            frame.set(button.location, if on { button.colors.on } else { button.colors.off });
+        }
+    }
+}
+
+/// The words of a mapping line, up to any comment
+fn words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for word in line.split_whitespace() {
+        let color = matches!(words.last(), Some(&"on") | Some(&"off"));
+        match word.find('#') {
+            Some(0) if color => words.push(word),
+            Some(0) => break,
+            Some(i) => {
+                words.push(&word[..i]);
+                break;
+            }
+            None => words.push(word),
+        }
+    }
+    words
+}
+
+/// Failure to load a mapping
+#[derive(Debug)]
+pub enum MappingError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MappingError::Io(ref err) => write!(f, "{}", err),
+            MappingError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for MappingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color::{LedColor, RGBColor};
+    use frame::pad_position;
+
+    #[test]
+    fn parse_and_play() {
+        let mut mapping = Mapping::parse("# drums\n\
+                                          pad 0 7 note 36 channel 10 # kick\n\
+                                          side 0 cc 64 toggle value 127 on #ff0000 #sustain\n\
+                                          top 0 program 3#patch\n\
+                                          #top 1 program 4\n")
+            .unwrap();
+        assert_eq!(mapping.buttons().len(), 3);
+        assert_eq!(mapping.buttons()[1].colors.on, LedColor::Rgb(RGBColor::new(255, 0, 0)));
+
+        let kick = pad_position(0, 7);
+        assert_eq!(mapping.handle(&Event::Press { position: kick, velocity: 90 }),
+                   vec![[0x99, 36, 90]]);
+        assert!(mapping.is_on(Location::Pad { x: 0, y: 7 }));
+        assert_eq!(mapping.handle(&Event::Release { position: kick }), vec![[0x89, 36, 0]]);
+
+        let sustain = Location::Side(0).position();
+        assert_eq!(mapping.handle(&Event::Press { position: sustain, velocity: 127 }),
+                   vec![[0xB0, 64, 127]]);
+        assert!(mapping.handle(&Event::Release { position: sustain }).is_empty());
+        assert_eq!(mapping.release_all(), vec![[0xB0, 64, 0]]);
+
+        assert!(Mapping::parse("pad 8 0 note 1").is_err());
+        assert!(Mapping::parse("pad 0 0 note #36").is_err());
+        assert!(Mapping::parse("pad 0 0 note 1 channel 17").is_err());
+    }
+}
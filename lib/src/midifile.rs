@@ -0,0 +1,309 @@
+//! Light shows as Standard MIDI Files.
+//!
+//! The messages a session sent to the device are written as a single track
+//! of notes, controls and sysex events, timed in milliseconds, so a show can
+//! be edited in a DAW and played back with `Session::play`. Pads are the note
+//! numbers of the session layout and the velocity is the palette color;
+//! notes on channel 2 flash and on channel 3 pulse.
+//!
+//! Any format 0 or 1 file can be read: the tracks are merged, tempo changes
+//! are followed, and a note off turns its LED off, so a note's length in the
+//! piano roll is how long the pad stays lit.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use session::{Flow, Session, SessionMessage};
+
+/// Ticks per quarter note written. With the tempo written, a tick is a
+/// millisecond.
+const DIVISION: u16 = 1000;
+/// Microseconds per quarter note, by default and as written
+const TEMPO: u32 = 1_000_000;
+
+impl Session {
+    /// The messages sent to the device as a Standard MIDI File
+    pub fn to_midi_file(&self) -> Vec<u8> {
+        let mut track = Vec::new();
+        // Tempo, so that a tick lasts a millisecond
+        track.extend_from_slice(&[0x00, 0xFF, 0x51, 0x03]);
+        track.extend_from_slice(&TEMPO.to_be_bytes()[1..]);
+
+        let mut last = 0;
+        for msg in self.messages.iter().filter(|m| m.flow == Flow::Out) {
+            // Real time messages such as the clock can't go in a file
+            if msg.bytes[0] >= 0xF8 {
+                continue;
+            }
+            push_number(&mut track, msg.time - last);
+            last = msg.time;
+            if msg.bytes[0] == 0xF0 {
+                track.push(0xF0);
+                push_number(&mut track, msg.bytes.len() as u32 - 1);
+                track.extend_from_slice(&msg.bytes[1..]);
+            } else {
+                track.extend_from_slice(&msg.bytes);
+            }
+        }
+        track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+        let mut file = Vec::new();
+        file.extend_from_slice(b"MThd");
+        file.extend_from_slice(&6u32.to_be_bytes());
+        file.extend_from_slice(&0u16.to_be_bytes());
+        file.extend_from_slice(&1u16.to_be_bytes());
+        file.extend_from_slice(&DIVISION.to_be_bytes());
+        file.extend_from_slice(b"MTrk");
+        file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        file.extend_from_slice(&track);
+        file
+    }
+
+    /// Read a Standard MIDI File as messages to send to the device
+    pub fn from_midi_file(data: &[u8]) -> Result<Session, MidiFileError> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(4)? != b"MThd" {
+            return Err(invalid("not a MIDI file"));
+        }
+        let header = reader.length()?;
+        let mut header = Reader { data: reader.take(header)?, pos: 0 };
+        let format = header.u16()?;
+        let tracks = header.u16()?;
+        let division = header.u16()?;
+        if format > 1 {
+            return Err(invalid("only format 0 and 1 files are supported"));
+        }
+
+        let mut events = Vec::new();
+        for _ in 0..tracks {
+            // Skip chunks of unknown types, as the specification asks
+            loop {
+                let kind = reader.take(4)?;
+                let length = reader.length()?;
+                let chunk = reader.take(length)?;
+                if kind == b"MTrk" {
+                    read_track(chunk, &mut events)?;
+                    break;
+                }
+            }
+        }
+        // Stable, so events at the same tick keep their track order
+        events.sort_by_key(|&(tick, _)| tick);
+
+        // Milliseconds per tick, from the tempo for metrical divisions
+        let per_tick = |tempo: u32| if division & 0x8000 == 0 {
+            tempo as f64 / 1000.0 / division.max(1) as f64
+        } else {
+            let frames = -((division >> 8) as i8) as f64;
+            let ticks = (division & 0xFF).max(1) as f64;
+            1000.0 / (frames * ticks)
+        };
+
+        let mut session = Session::new();
+        let (mut tick, mut time, mut tempo) = (0, 0.0, TEMPO);
+        for (at, event) in events {
+            time += (at - tick) as f64 * per_tick(tempo);
+            tick = at;
+            match event {
+                TrackEvent::Tempo(t) => tempo = t,
+                TrackEvent::Message(bytes) => {
+                    session.messages.push(SessionMessage {
+                        time: time.round() as u32,
+                        flow: Flow::Out,
+                        bytes,
+                    })
+                }
+            }
+        }
+        Ok(session)
+    }
+
+    /// Load a Standard MIDI File
+    pub fn load_midi_file<P: AsRef<Path>>(path: P) -> Result<Session, MidiFileError> {
+        let data = fs::read(path).map_err(MidiFileError::Io)?;
+        Session::from_midi_file(&data)
+    }
+
+    /// Save the messages sent to the device as a Standard MIDI File
+    pub fn save_midi_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_midi_file())
+    }
+}
+
+enum TrackEvent {
+    Tempo(u32),
+    Message(Vec<u8>),
+}
+
+/// Add the events of a track, with the tick they happen at
+fn read_track(chunk: &[u8], events: &mut Vec<(u64, TrackEvent)>) -> Result<(), MidiFileError> {
+    let mut reader = Reader { data: chunk, pos: 0 };
+    let mut tick = 0u64;
+    let mut running = None;
+
+    while reader.pos < chunk.len() {
+        tick += reader.number()? as u64;
+        let mut status = reader.byte()?;
+        if status < 0x80 {
+            // Running status: this byte is the first data byte
+            reader.pos -= 1;
+            status = running.ok_or_else(|| invalid("data without a status"))?;
+        }
+
+        match status {
+            0xFF => {
+                let kind = reader.byte()?;
+                let length = reader.number()? as usize;
+                let data = reader.take(length)?;
+                match (kind, data) {
+                    (0x2F, _) => break,
+                    (0x51, &[a, b, c]) => {
+                        events.push((tick, TrackEvent::Tempo(u32::from_be_bytes([0, a, b, c]))))
+                    }
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                let length = reader.number()? as usize;
+                let data = reader.take(length)?;
+                // Only whole messages are sent; escaped pieces are dropped
+                if status == 0xF0 && data.last() == Some(&0xF7) {
+                    let mut bytes = vec![0xF0];
+                    bytes.extend_from_slice(data);
+                    events.push((tick, TrackEvent::Message(bytes)));
+                }
+            }
+            0x80..=0xEF => {
+                running = Some(status);
+                let first = reader.byte()?;
+                let second = match status & 0xF0 {
+                    0xC0 | 0xD0 => None,
+                    _ => Some(reader.byte()?),
+                };
+                let bytes = match (status & 0xF0, second) {
+                    // A note off turns the LED off, whichever way it was lit
+                    (0x80, _) => vec![0x90, first, 0],
+                    (_, Some(second)) => vec![status, first, second],
+                    (_, None) => continue,
+                };
+                events.push((tick, TrackEvent::Message(bytes)));
+            }
+            _ => return Err(invalid("unexpected status byte")),
+        }
+    }
+    Ok(())
+}
+
+/// Append a variable length number
+fn push_number(data: &mut Vec<u8>, number: u32) {
+    let mut bytes = vec![(number & 0x7F) as u8];
+    let mut rest = number >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    data.extend(bytes.iter().rev());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MidiFileError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or_else(|| invalid("file too short"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, MidiFileError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiFileError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn length(&mut self) -> Result<usize, MidiFileError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    /// A variable length number
+    fn number(&mut self) -> Result<u32, MidiFileError> {
+        let mut number = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            number = (number << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(number);
+            }
+        }
+        Err(invalid("variable length number too long"))
+    }
+}
+
+fn invalid(message: &str) -> MidiFileError {
+    MidiFileError::Invalid(message.to_string())
+}
+
+/// Failure to read a Standard MIDI File
+#[derive(Debug)]
+pub enum MidiFileError {
+    Io(io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for MidiFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MidiFileError::Io(ref err) => write!(f, "{}", err),
+            MidiFileError::Invalid(ref message) => write!(f, "invalid MIDI file: {}", message),
+        }
+    }
+}
+
+impl Error for MidiFileError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let session = Session::parse("0 out F0 00 20 29 02 18 0E 00 F7\n\
+                                      0 out 90 0B 05\n\
+                                      250 in 90 0B 7F\n\
+                                      300 out F8 00 00\n\
+                                      1500 out 92 51 2D\n")
+            .unwrap();
+        let read = Session::from_midi_file(&session.to_midi_file()).unwrap();
+        assert_eq!(read.to_string(),
+                   "# launch-rs session\n\
+                    0 out F0 00 20 29 02 18 0E 00 F7\n\
+                    0 out 90 0B 05\n\
+                    1500 out 92 51 2D\n");
+    }
+
+    #[test]
+    fn daw_file() {
+        // Format 1 at 96 ticks per quarter, 120 BPM in the first track, and a
+        // note with running status ended by a note off in the second
+        let mut file = b"MThd\x00\x00\x00\x06\x00\x01\x00\x02\x00\x60".to_vec();
+        file.extend_from_slice(b"MTrk\x00\x00\x00\x0B\x00\xFF\x51\x03\x07\xA1\x20\x00\xFF\x2F\x00");
+        file.extend_from_slice(b"MTrk\x00\x00\x00\x0B\x00\x91\x0B\x05\x60\x0C\x06\x30\x81\x0B\x40");
+        let read = Session::from_midi_file(&file).unwrap();
+        assert_eq!(read.to_string(),
+                   "# launch-rs session\n\
+                    0 out 91 0B 05\n\
+                    500 out 91 0C 06\n\
+                    750 out 90 0B 00\n");
+
+        assert!(Session::from_midi_file(b"RIFF").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! Multiple virtual screens switched with the top or side buttons.
+//!
+//! A `PageManager` owns up to eight `Page`s. Pressing one of the switching
+//! buttons brings its page on screen, and the buttons show which page is
+//! current. Pages off screen keep their state and can still be changed.
+
+use std::any::Any;
+
+use color::LedColor;
+use event::Event;
+use frame::{Frame, Location, GRID_SIZE};
+use launchpad::LaunchpadMk2;
+use widget::{Colors, Panel};
+
+/// A virtual screen
+pub trait Page {
+    /// React to an event on the pads or on the buttons not used for switching
+    fn handle(&mut self, event: &Event);
+
+    /// Draw the page. The switching buttons are drawn over by the manager.
+    fn draw(&self, frame: &mut Frame);
+}
+
+impl Page for Panel {
+    fn handle(&mut self, event: &Event) {
+        Panel::handle(self, event);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        Panel::draw(self, frame);
+    }
+}
+
+/// Reacts to an event on a `FramePage`, drawing onto its frame
+type FrameHandler = Box<dyn FnMut(&Event, &mut Frame)>;
+
+/// A page which is just a frame, changed by an event handler
+pub struct FramePage {
+    pub frame: Frame,
+    handler: Option<FrameHandler>,
+}
+
+impl FramePage {
+    /// A page showing a fixed frame
+    pub fn new(frame: Frame) -> FramePage {
+        FramePage {
+            frame,
+            handler: None,
+        }
+    }
+
+    /// A page whose frame is changed by `handler` as events arrive
+    pub fn with_handler<F: FnMut(&Event, &mut Frame) + 'static>(frame: Frame, handler: F) -> FramePage {
+        FramePage {
+            frame,
+            handler: Some(Box::new(handler)),
+        }
+    }
+}
+
+impl Page for FramePage {
+    fn handle(&mut self, event: &Event) {
+        if let Some(ref mut handler) = self.handler {
+            handler(event, &mut self.frame);
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        *frame = self.frame.clone();
+    }
+}
+
+/// Which row of buttons switches pages
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Switcher {
+    /// The top buttons, page 0 on the left
+    Top,
+    /// The side buttons, page 0 at the top
+    Side,
+}
+
+impl Switcher {
+    fn location(self, page: usize) -> Location {
+        match self {
+            Switcher::Top => Location::Top(page),
+            Switcher::Side => Location::Side(page),
+        }
+    }
+
+    fn page(self, location: Location) -> Option<usize> {
+        match (self, location) {
+            (Switcher::Top, Location::Top(index)) | (Switcher::Side, Location::Side(index)) => Some(index),
+            _ => None,
+        }
+    }
+}
+
+/// A page which can be handed back as its own type
+trait AnyPage: Page {
+    fn as_page(&self) -> &dyn Page;
+    fn as_page_mut(&mut self) -> &mut dyn Page;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<P: Page + 'static> AnyPage for P {
+    fn as_page(&self) -> &dyn Page {
+        self
+    }
+
+    fn as_page_mut(&mut self) -> &mut dyn Page {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Switches between pages
+pub struct PageManager {
+    pages: Vec<Box<dyn AnyPage>>,
+    current: usize,
+    /// Buttons held down, with the page their press went to
+    held: Vec<(u8, usize)>,
+    switcher: Switcher,
+    /// `on` for the current page's button, `off` for other pages'
+    colors: Colors,
+    shown: Option<Frame>,
+}
+
+impl PageManager {
+    pub fn new(switcher: Switcher, colors: Colors) -> PageManager {
+        PageManager {
+            pages: Vec::new(),
+            current: 0,
+            held: Vec::new(),
+            switcher,
+            colors,
+            shown: None,
+        }
+    }
+
+    /// Add a page, switched to by the next unused button. Returns its index.
+    pub fn add<P: Page + 'static>(&mut self, page: P) -> usize {
+        assert!(self.pages.len() < GRID_SIZE, "Too Many Pages!");
+        self.pages.push(Box::new(page));
+        self.pages.len() - 1
+    }
+
+    /// Index of the page on screen
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Bring a page on screen
+    pub fn select(&mut self, page: usize) {
+        assert!(page < self.pages.len(), "Bad Page!");
+        self.current = page;
+    }
+
+    /// A page, on screen or not
+    pub fn page(&self, page: usize) -> &dyn Page {
+        self.pages[page].as_page()
+    }
+
+    /// A page, on screen or not, to change its state
+    pub fn page_mut(&mut self, page: usize) -> &mut dyn Page {
+        self.pages[page].as_page_mut()
+    }
+
+    /// A page as its own type, e.g. to read a `FramePage`'s frame. `None` if
+    /// the page is of another type.
+    pub fn page_as<P: Page + 'static>(&self, page: usize) -> Option<&P> {
+        self.pages[page].as_any().downcast_ref()
+    }
+
+    /// A page as its own type, to change its state
+    pub fn page_as_mut<P: Page + 'static>(&mut self, page: usize) -> Option<&mut P> {
+        self.pages[page].as_any_mut().downcast_mut()
+    }
+
+    /// Switch pages on presses of the switching buttons, and pass every other
+    /// event to the current page. A release goes to the page its press went
+    /// to, even if the current page changed in between.
+    pub fn handle(&mut self, event: &Event) {
+        let mut target = self.current;
+        if let Event::Press { position, .. } | Event::Release { position } = *event {
+            if let Some(page) = Location::of(position).and_then(|l| self.switcher.page(l)) {
+                if let Event::Press { .. } = *event {
+                    if page < self.pages.len() {
+                        self.current = page;
+                    }
+                }
+                return;
+            }
+            let held = self.held.iter().position(|&(p, _)| p == position);
+            match (*event, held) {
+                (Event::Press { .. }, None) => self.held.push((position, self.current)),
+                (Event::Release { .. }, Some(index)) => target = self.held.swap_remove(index).1,
+                _ => {}
+            }
+        }
+        if let Some(page) = self.pages.get_mut(target) {
+            page.handle(event);
+        }
+    }
+
+    /// Draw the current page and the switching buttons
+    pub fn draw(&self, frame: &mut Frame) {
+        if let Some(page) = self.pages.get(self.current) {
+            page.draw(frame);
+        }
+        for i in 0..GRID_SIZE {
+            let color = if i >= self.pages.len() {
+                LedColor::Off
+            } else if i == self.current {
+                self.colors.on
+            } else {
+                self.colors.off
+            };
+            frame.set(self.switcher.location(i), color);
+        }
+    }
+
+    /// Handle pending input from the device and light the LEDs which
+    /// changed. Call this regularly from the application's loop.
+    pub fn update(&mut self, lpad: &mut LaunchpadMk2) {
+        for event in lpad.poll_events() {
+            self.handle(&event);
+        }
+
+        let mut frame = Frame::new();
+        self.draw(&mut frame);
+        match self.shown {
+            Some(ref shown) => lpad.draw_frame_diff(&frame, shown),
+            None => lpad.draw_frame(&frame),
+        }
+        self.shown = Some(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame::{pad_position, side_position, top_position};
+    use palette;
+    use widget::{Button, WidgetEvent};
+
+    fn press(position: u8) -> Event {
+        Event::Press {
+            position,
+            velocity: 127,
+        }
+    }
+
+    #[test]
+    fn switching() {
+        let mut pages = PageManager::new(Switcher::Top, Colors::new(palette::RED, palette::BLUE));
+        let mut first = Frame::new();
+        first.set_pad(0, 0, palette::WHITE);
+        pages.add(FramePage::new(first));
+        pages.add(FramePage::with_handler(Frame::new(), |event, frame| {
+            if let Event::Press { position, .. } = *event {
+                frame.set(Location::of(position).unwrap(), palette::GREEN);
+            }
+        }));
+
+        let mut frame = Frame::new();
+        pages.draw(&mut frame);
+        assert_eq!(frame.pad(0, 0), LedColor::Palette(palette::WHITE));
+        assert_eq!(frame.top(0), LedColor::Palette(palette::RED));
+        assert_eq!(frame.top(1), LedColor::Palette(palette::BLUE));
+        assert_eq!(frame.top(2), LedColor::Off);
+
+        // Buttons without a page don't switch
+        pages.handle(&press(top_position(5)));
+        assert_eq!(pages.current(), 0);
+        pages.handle(&press(top_position(1)));
+        assert_eq!(pages.current(), 1);
+        pages.handle(&press(pad_position(3, 4)));
+        let second = pages.page_as::<FramePage>(1).unwrap();
+        assert_eq!(second.frame.pad(3, 4), LedColor::Palette(palette::GREEN));
+        assert!(pages.page_as::<Panel>(1).is_none());
+    }
+
+    #[test]
+    fn release_follows_press() {
+        let mut pages = PageManager::new(Switcher::Side, Colors::new(palette::RED, LedColor::Off));
+        for _ in 0..2 {
+            let mut panel = Panel::new();
+            panel.add(Button::new(Location::Pad { x: 0, y: 0 }, Colors::new(palette::RED, LedColor::Off)));
+            pages.add(panel);
+        }
+
+        let pad = pad_position(0, 0);
+        pages.handle(&press(pad));
+        pages.handle(&press(side_position(1)));
+        pages.handle(&Event::Release { position: pad });
+        let button = |pages: &PageManager, page| {
+            pages.page_as::<Panel>(page).and_then(|panel| panel.widget_as::<Button>(0)).unwrap().pressed
+        };
+        assert!(!button(&pages, 0));
+        assert!(!button(&pages, 1));
+
+        // A release without a press goes to the current page
+        let panel = pages.page_as_mut::<Panel>(1).unwrap();
+        assert_eq!(panel.handle(&press(pad)), Some((0, WidgetEvent::Pressed)));
+        pages.handle(&Event::Release { position: pad });
+        assert!(!button(&pages, 1));
+    }
+}
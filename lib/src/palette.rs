@@ -0,0 +1,257 @@
+//! Human names for the 128 colors of the Mark 2 palette.
+//!
+//! Every name is a plain `Color`, so they can be used anywhere a palette
+//! index is accepted today:
+//!
+//! ```no_run
+//! use launchpad::*;
+//!
+//! let mut lpad = LaunchpadMk2::guess();
+//! lpad.light_all(palette::OFF);
+//! lpad.light_row(&ColorRow { row: 0, color: palette::dim(palette::RED) });
+//! ```
+//!
+//! Palette colors 4 through 59 are laid out as ramps of four, from a light
+//! tint through the full color to dim and dark versions, and 0 through 3
+//! ramp from off to white. `dim` and `bright` walk along those ramps.
+
+use launchpad::Color;
+
+macro_rules! palette_colors {
+    ($($name:ident = $index:expr,)*) => {
+        $(pub const $name: Color = $index;)*
+
+        /// Every named color and its name, in palette order
+        pub const NAMES: [(&str, Color); 128] = [$((stringify!($name), $index),)*];
+    }
+}
+
+palette_colors! {
+    // Greys, from off to white
+    OFF = 0,
+    DARK_GREY = 1,
+    GREY = 2,
+    WHITE = 3,
+    // Hue ramps (4...59), each light, full, dim and dark
+    RED_LIGHT = 4,
+    RED = 5,
+    RED_DIM = 6,
+    RED_DARK = 7,
+
+    ORANGE_LIGHT = 8,
+    ORANGE = 9,
+    ORANGE_DIM = 10,
+    ORANGE_DARK = 11,
+
+    YELLOW_LIGHT = 12,
+    YELLOW = 13,
+    YELLOW_DIM = 14,
+    YELLOW_DARK = 15,
+
+    LIME_LIGHT = 16,
+    LIME = 17,
+    LIME_DIM = 18,
+    LIME_DARK = 19,
+
+    GREEN_LIGHT = 20,
+    GREEN = 21,
+    GREEN_DIM = 22,
+    GREEN_DARK = 23,
+
+    EMERALD_LIGHT = 24,
+    EMERALD = 25,
+    EMERALD_DIM = 26,
+    EMERALD_DARK = 27,
+
+    SPRING_LIGHT = 28,
+    SPRING = 29,
+    SPRING_DIM = 30,
+    SPRING_DARK = 31,
+
+    TURQUOISE_LIGHT = 32,
+    TURQUOISE = 33,
+    TURQUOISE_DIM = 34,
+    TURQUOISE_DARK = 35,
+
+    CYAN_LIGHT = 36,
+    CYAN = 37,
+    CYAN_DIM = 38,
+    CYAN_DARK = 39,
+
+    AZURE_LIGHT = 40,
+    AZURE = 41,
+    AZURE_DIM = 42,
+    AZURE_DARK = 43,
+
+    BLUE_LIGHT = 44,
+    BLUE = 45,
+    BLUE_DIM = 46,
+    BLUE_DARK = 47,
+
+    VIOLET_LIGHT = 48,
+    VIOLET = 49,
+    VIOLET_DIM = 50,
+    VIOLET_DARK = 51,
+
+    MAGENTA_LIGHT = 52,
+    MAGENTA = 53,
+    MAGENTA_DIM = 54,
+    MAGENTA_DARK = 55,
+
+    PINK_LIGHT = 56,
+    PINK = 57,
+    PINK_DIM = 58,
+    PINK_DARK = 59,
+
+    // Odds and ends at the end of the ramps
+    SCARLET = 60,
+    BROWN = 61,
+    OCHRE = 62,
+    OLIVE = 63,
+
+    // Extended colors (64...127), not arranged in ramps
+    FOREST = 64,
+    SEA_GREEN = 65,
+    TEAL = 66,
+    PURE_BLUE = 67,
+    DARK_TEAL = 68,
+    INDIGO = 69,
+    MID_GREY = 70,
+    CHARCOAL = 71,
+    PURE_RED = 72,
+    CHARTREUSE_LIGHT = 73,
+    CHARTREUSE = 74,
+    BRIGHT_LIME = 75,
+    MEDIUM_GREEN = 76,
+    MINT_GREEN = 77,
+    SKY = 78,
+    ROYAL_BLUE = 79,
+    ULTRAMARINE = 80,
+    PURPLE = 81,
+    PLUM = 82,
+    DARK_BROWN = 83,
+    BRIGHT_ORANGE = 84,
+    YELLOW_GREEN = 85,
+    LAWN_GREEN = 86,
+    PURE_GREEN = 87,
+    NEON_GREEN = 88,
+    LIGHT_GREEN = 89,
+    AQUAMARINE = 90,
+    CORNFLOWER = 91,
+    STEEL_BLUE = 92,
+    LAVENDER = 93,
+    ORCHID = 94,
+    CRIMSON = 95,
+    AMBER = 96,
+    MUSTARD = 97,
+    GREEN_YELLOW = 98,
+    BRONZE = 99,
+    DARK_BRONZE = 100,
+    DARK_FOREST = 101,
+    DARK_SEA_GREEN = 102,
+    DARK_NAVY = 103,
+    NAVY = 104,
+    TAN = 105,
+    DARK_RED = 106,
+    SALMON = 107,
+    BURNT_ORANGE = 108,
+    GOLD = 109,
+    PEAR = 110,
+    GRASS = 111,
+    MIDNIGHT = 112,
+    PALE_LIME = 113,
+    MINT = 114,
+    PERIWINKLE = 115,
+    LIGHT_PURPLE = 116,
+    DIM_GREY = 117,
+    SLATE_GREY = 118,
+    ICE = 119,
+    BLOOD_RED = 120,
+    MAROON = 121,
+    KELLY_GREEN = 122,
+    DEEP_GREEN = 123,
+    KHAKI = 124,
+    DARK_OLIVE = 125,
+    COPPER = 126,
+    RUST = 127,
+}
+
+/// First color of the hue ramps
+const RAMPS_START: Color = 4;
+/// One past the last color of the hue ramps
+const RAMPS_END: Color = 60;
+
+/// Whether the color is part of one of the light/full/dim/dark hue ramps
+pub fn in_ramp(color: Color) -> bool {
+    (RAMPS_START..RAMPS_END).contains(&color)
+}
+
+/// Position of a hue ramp color within its ramp, 0 being the light tint
+fn ramp_step(color: Color) -> u8 {
+    (color - RAMPS_START) % 4
+}
+
+/// The next dimmer color along the palette's brightness ramps. Colors which
+/// are already the darkest of their ramp, or not part of a ramp, are
+/// returned unchanged.
+pub fn dim(color: Color) -> Color {
+    match color {
+        1..=3 => color - 1,
+        _ if in_ramp(color) && ramp_step(color) < 3 => color + 1,
+        _ => color,
+    }
+}
+
+/// The next brighter color along the palette's brightness ramps. Colors
+/// which are already the brightest of their ramp, or not part of a ramp, are
+/// returned unchanged.
+pub fn bright(color: Color) -> Color {
+    match color {
+        0..=2 => color + 1,
+        _ if in_ramp(color) && ramp_step(color) > 0 => color - 1,
+        _ => color,
+    }
+}
+
+/// The name of a palette color, e.g. `"RED_DIM"`
+pub fn name(color: Color) -> Option<&'static str> {
+    NAMES.get(color as usize).map(|&(name, _)| name)
+}
+
+/// Look up a palette color by name. Case is ignored, and `-` or spaces may
+/// be used in place of `_`, so `"red dim"` finds `RED_DIM`.
+pub fn by_name(name: &str) -> Option<Color> {
+    let wanted: String = name.trim()
+        .chars()
+        .map(|c| if c == '-' || c == ' ' { '_' } else { c.to_ascii_uppercase() })
+        .collect();
+    NAMES.iter().find(|&&(n, _)| n == wanted).map(|&(_, color)| color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps() {
+        assert_eq!(dim(RED), RED_DIM);
+        assert_eq!(dim(RED_DARK), RED_DARK);
+        assert_eq!(bright(RED), RED_LIGHT);
+        assert_eq!(bright(RED_LIGHT), RED_LIGHT);
+        assert_eq!(dim(GREY), DARK_GREY);
+        assert_eq!(bright(AMBER), AMBER);
+        assert_eq!(dim(PINK_DIM), PINK_DARK);
+        for &color in &[SCARLET, BROWN, OCHRE, OLIVE] {
+            assert!(!in_ramp(color));
+            assert_eq!(dim(color), color);
+            assert_eq!(bright(color), color);
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(name(AMBER), Some("AMBER"));
+        assert_eq!(by_name("red dim"), Some(RED_DIM));
+        assert_eq!(by_name("not-a-color"), None);
+    }
+}
@@ -0,0 +1,162 @@
+//! Building messages for the device's own text scrolling.
+
+use std::error::Error;
+use std::fmt;
+
+/// Speeds understood by `scroll_text`, slowest first
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ScrollSpeed {
+    Slowest = 1,
+    Slower = 2,
+    Slow = 3,
+    Normal = 4,
+    Fast = 5,
+    Faster = 6,
+    Fastest = 7,
+}
+
+impl ScrollSpeed {
+    /// The control character which switches to this speed mid-message
+    pub fn control_char(self) -> char {
+        self as u8 as char
+    }
+}
+
+/// Text for `scroll_text`, with speed changes embedded between runs of text.
+///
+/// ```
+/// use launchpad::*;
+///
+/// let text = ScrollText::new()
+///     .speed(ScrollSpeed::Slower)
+///     .text("Your ")
+///     .speed(ScrollSpeed::Faster)
+///     .text("Turn!");
+/// assert_eq!(text.as_str(), "\u{02}Your \u{06}Turn!");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrollText {
+    text: String,
+}
+
+impl ScrollText {
+    pub fn new() -> ScrollText {
+        ScrollText::default()
+    }
+
+    /// Change the speed for the text which follows
+    pub fn speed(mut self, speed: ScrollSpeed) -> ScrollText {
+        self.text.push(speed.control_char());
+        self
+    }
+
+    /// Add text, transliterating characters the device cannot show into
+    /// ASCII: accents are dropped, typographic quotes and dashes become plain
+    /// ones, and anything else becomes `?`.
+    pub fn text(mut self, text: &str) -> ScrollText {
+        for c in text.chars() {
+            match c {
+                ' '..='~' => self.text.push(c),
+                _ => self.text.push_str(transliterate(c)),
+            }
+        }
+        self
+    }
+
+    /// Add text, failing if any character is not printable ASCII
+    pub fn ascii(mut self, text: &str) -> Result<ScrollText, NonAsciiError> {
+        if let Some((index, character)) = text.char_indices().find(|&(_, c)| !is_printable(c)) {
+            return Err(NonAsciiError { character, index });
+        }
+        self.text.push_str(text);
+        Ok(self)
+    }
+
+    /// The message, ready for `scroll_text`
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Whether the device can scroll a character
+pub fn is_printable(c: char) -> bool {
+    (' '..='~').contains(&c)
+}
+
+/// A character `scroll_text` can't show
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NonAsciiError {
+    pub character: char,
+    /// Byte index of the character in the text
+    pub index: usize,
+}
+
+impl fmt::Display for NonAsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "character {:?} at {} can not be scrolled",
+               self.character,
+               self.index)
+    }
+}
+
+impl Error for NonAsciiError {}
+
+/// An ASCII stand-in for a non-ASCII character
+pub(crate) fn transliterate(c: char) -> &'static str {
+    match c {
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => "A",
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => "a",
+        'Æ' => "AE",
+        'æ' => "ae",
+        'Ç' => "C",
+        'ç' => "c",
+        'È' | 'É' | 'Ê' | 'Ë' => "E",
+        'è' | 'é' | 'ê' | 'ë' => "e",
+        'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+        'ì' | 'í' | 'î' | 'ï' => "i",
+        'Ñ' => "N",
+        'ñ' => "n",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' => "O",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => "o",
+        'Œ' => "OE",
+        'œ' => "oe",
+        'Ù' | 'Ú' | 'Û' | 'Ü' => "U",
+        'ù' | 'ú' | 'û' | 'ü' => "u",
+        'Ý' | 'Ÿ' => "Y",
+        'ý' | 'ÿ' => "y",
+        'ß' => "ss",
+        '‘' | '’' | '‚' | '′' => "'",
+        '“' | '”' | '„' | '″' => "\"",
+        '–' | '—' | '‐' | '−' => "-",
+        '…' => "...",
+        '×' => "x",
+        '\t' | '\n' | '\r' | '\u{a0}' => " ",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transliteration() {
+        let text = ScrollText::new().text("“Crème brûlée” – 2×½\n");
+        assert_eq!(text.as_str(), "\"Creme brulee\" - 2x? ");
+        assert!(text.as_str().chars().all(is_printable));
+    }
+
+    #[test]
+    fn ascii_only() {
+        let text = ScrollText::new().speed(ScrollSpeed::Fast).ascii("Hi!").unwrap();
+        assert_eq!(text.as_str(), "\u{05}Hi!");
+
+        let err = ScrollText::new().ascii("Olá\n").unwrap_err();
+        assert_eq!(err, NonAsciiError { character: 'á', index: 2 });
+        assert_eq!(err.to_string(), "character 'á' at 2 can not be scrolled");
+        assert!(ScrollText::new().ascii("tab\t").is_err());
+    }
+}
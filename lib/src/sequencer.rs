@@ -0,0 +1,273 @@
+//! A step sequencer played on the grid.
+//!
+//! Rows of pads are tracks and columns are steps. Pressing a pad toggles its
+//! step, the top buttons pick which eight steps are on screen, and the side
+//! buttons mute tracks. The `Sequencer` only keeps the pattern and works out
+//! which MIDI messages to send; the caller decides when steps happen and
+//! where the messages go.
+
+use color::LedColor;
+use event::Event;
+use frame::{Frame, Location, GRID_SIZE};
+use palette;
+
+/// MIDI clocks per quarter note
+pub const CLOCKS_PER_BEAT: u32 = 24;
+/// Steps per quarter note; each step is a sixteenth note
+pub const STEPS_PER_BEAT: u32 = 4;
+
+/// The note a track plays
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SeqTrack {
+    pub note: u8,
+    /// MIDI channel, 0...15. Only the low four bits are sent.
+    pub channel: u8,
+    pub velocity: u8,
+    /// Color of the track's set steps
+    pub color: LedColor,
+    pub muted: bool,
+}
+
+/// General MIDI drum notes for the default tracks, top row first
+const DEFAULT_NOTES: [u8; GRID_SIZE] = [49, 46, 42, 39, 50, 45, 38, 36];
+
+/// Eight tracks of a pattern up to 64 steps long
+#[derive(Debug, Clone)]
+pub struct Sequencer {
+    tracks: [SeqTrack; GRID_SIZE],
+    steps: Vec<[bool; GRID_SIZE]>,
+    page: usize,
+    /// Step played last, `None` when stopped
+    playhead: Option<usize>,
+    /// Notes sounding from the last step, to be released on the next one
+    sounding: Vec<(u8, u8)>,
+    clocks: u32,
+}
+
+impl Sequencer {
+    /// A sequencer with `length` steps (8, 16, 32 or 64) and General MIDI
+    /// drums on channel 10
+    pub fn new(length: usize) -> Sequencer {
+        assert!([8, 16, 32, 64].contains(&length), "Bad Length!");
+        let colors = [palette::YELLOW, palette::ORANGE, palette::AMBER, palette::PINK,
+                      palette::VIOLET, palette::AZURE, palette::CYAN, palette::RED];
+        let mut tracks = [SeqTrack {
+            note: 0,
+            channel: 9,
+            velocity: 100,
+            color: LedColor::Off,
+            muted: false,
+        }; GRID_SIZE];
+        for (i, track) in tracks.iter_mut().enumerate() {
+            track.note = DEFAULT_NOTES[i];
+            track.color = LedColor::Palette(colors[i]);
+        }
+
+        Sequencer {
+            tracks,
+            steps: vec![[false; GRID_SIZE]; length],
+            page: 0,
+            playhead: None,
+            sounding: Vec::new(),
+            clocks: 0,
+        }
+    }
+
+    /// Number of steps in the pattern
+    pub fn length(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// A track, 0 being the top row
+    pub fn track(&self, track: usize) -> &SeqTrack {
+        &self.tracks[track]
+    }
+
+    /// Change a track's note, channel, velocity or color
+    pub fn track_mut(&mut self, track: usize) -> &mut SeqTrack {
+        &mut self.tracks[track]
+    }
+
+    /// Whether a track plays on a step
+    pub fn step(&self, track: usize, step: usize) -> bool {
+        self.steps[step][track]
+    }
+
+    /// Turn a track's step on or off
+    pub fn set_step(&mut self, track: usize, step: usize, on: bool) {
+        self.steps[step][track] = on;
+    }
+
+    /// The step played last, if playing
+    pub fn playhead(&self) -> Option<usize> {
+        self.playhead
+    }
+
+    /// React to a press: pads toggle steps on the page shown, top buttons
+    /// show a page, and side buttons mute tracks
+    pub fn handle(&mut self, event: &Event) {
+        let position = match *event {
+            Event::Press { position, .. } => position,
+            _ => return,
+        };
+        match Location::of(position) {
+            Some(Location::Pad { x, y }) => {
+                let step = self.page * GRID_SIZE + x;
+                self.steps[step][y] = !self.steps[step][y];
+            }
+            Some(Location::Top(page)) if page < self.length() / GRID_SIZE => self.page = page,
+            Some(Location::Side(track)) => self.tracks[track].muted = !self.tracks[track].muted,
+            _ => {}
+        }
+    }
+
+    /// Draw the page shown, the playhead, the pages and the mutes
+    pub fn draw(&self, frame: &mut Frame) {
+        let first = self.page * GRID_SIZE;
+        for x in 0..GRID_SIZE {
+            let step = first + x;
+            let at_playhead = self.playhead == Some(step);
+            for (y, track) in self.tracks.iter().enumerate() {
+                let color = match (self.steps[step][y], at_playhead) {
+                    (true, true) => LedColor::Palette(palette::WHITE),
+                    (true, false) => track.color,
+                    (false, true) => LedColor::Palette(palette::DARK_GREY),
+                    (false, false) => LedColor::Off,
+                };
+                frame.set_pad(x, y, color);
+            }
+        }
+
+        let pages = self.length() / GRID_SIZE;
+        let playing_page = self.playhead.map(|step| step / GRID_SIZE);
+        for i in 0..GRID_SIZE {
+            let color = if i >= pages {
+                palette::OFF
+            } else if i == self.page {
+                palette::GREEN
+            } else if Some(i) == playing_page {
+                palette::GREEN_DARK
+            } else {
+                palette::DARK_GREY
+            };
+            frame.set_top(i, color);
+            frame.set_side(i, if self.tracks[i].muted { palette::RED_DIM } else { palette::OFF });
+        }
+    }
+
+    /// Move to the next step, returning the note offs for the previous step
+    /// and note ons for this one
+    pub fn advance(&mut self) -> Vec<[u8; 3]> {
+        let mut msgs = self.release();
+        let step = self.playhead.map_or(0, |step| (step + 1) % self.length());
+        self.playhead = Some(step);
+
+        for (y, track) in self.tracks.iter().enumerate() {
+            if self.steps[step][y] && !track.muted {
+                let (channel, note) = (track.channel & 0x0F, track.note & 0x7F);
+                msgs.push([0x90 | channel, note, track.velocity & 0x7F]);
+                self.sounding.push((channel, note));
+            }
+        }
+        msgs
+    }
+
+    /// Stop playing, returning note offs for anything still sounding. The
+    /// next `advance` starts from the first step.
+    pub fn stop(&mut self) -> Vec<[u8; 3]> {
+        self.playhead = None;
+        self.clocks = 0;
+        self.release()
+    }
+
+    fn release(&mut self) -> Vec<[u8; 3]> {
+        self.sounding.drain(..).map(|(channel, note)| [0x80 | channel, note, 0]).collect()
+    }
+
+    /// Follow a MIDI clock: advance every sixth clock (0xF8), restart on
+    /// start (0xFA) and stop on stop (0xFC). Returns the messages to send.
+    pub fn clock(&mut self, status: u8) -> Vec<[u8; 3]> {
+        match status {
+            0xF8 => {
+                let due = self.clocks % (CLOCKS_PER_BEAT / STEPS_PER_BEAT) == 0;
+                self.clocks += 1;
+                if due { self.advance() } else { Vec::new() }
+            }
+            0xFA => self.stop(),
+            0xFC => self.stop(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame::{pad_position, side_position, top_position};
+
+    fn press(position: u8) -> Event {
+        Event::Press {
+            position,
+            velocity: 127,
+        }
+    }
+
+    #[test]
+    fn steps_and_pages() {
+        let mut seq = Sequencer::new(16);
+        seq.handle(&press(pad_position(2, 7)));
+        assert!(seq.step(7, 2));
+        seq.handle(&Event::Release { position: pad_position(2, 7) });
+        assert!(seq.step(7, 2));
+
+        // Pages past the end of the pattern are not shown
+        seq.handle(&press(top_position(2)));
+        seq.handle(&press(top_position(1)));
+        seq.handle(&press(pad_position(2, 7)));
+        assert!(seq.step(7, 10));
+        seq.handle(&press(pad_position(2, 7)));
+        assert!(!seq.step(7, 10));
+
+        let mut frame = Frame::new();
+        seq.draw(&mut frame);
+        assert_eq!(frame.top(1), LedColor::Palette(palette::GREEN));
+        assert_eq!(frame.top(2), LedColor::Palette(palette::OFF));
+        assert_eq!(frame.pad(2, 7), LedColor::Off);
+    }
+
+    #[test]
+    fn playing() {
+        let mut seq = Sequencer::new(8);
+        seq.set_step(7, 0, true);
+        seq.set_step(6, 0, true);
+        seq.set_step(7, 1, true);
+        assert_eq!(seq.advance(), vec![[0x99, 38, 100], [0x99, 36, 100]]);
+        assert_eq!(seq.playhead(), Some(0));
+
+        // Muted tracks are silent
+        seq.handle(&press(side_position(6)));
+        assert!(seq.track(6).muted);
+        assert_eq!(seq.advance(), vec![[0x89, 38, 0], [0x89, 36, 0], [0x99, 36, 100]]);
+
+        // Out of range channels are kept to 0...15
+        seq.track_mut(7).channel = 0x1A;
+        for _ in 2..8 {
+            seq.advance();
+        }
+        assert_eq!(seq.advance(), vec![[0x9A, 36, 100]]);
+        assert_eq!(seq.stop(), vec![[0x8A, 36, 0]]);
+        assert_eq!(seq.playhead(), None);
+    }
+
+    #[test]
+    fn midi_clock() {
+        let mut seq = Sequencer::new(8);
+        seq.set_step(0, 1, true);
+        let steps: Vec<_> = (0..12).map(|_| seq.clock(0xF8)).collect();
+        assert_eq!(steps.iter().filter(|msgs| !msgs.is_empty()).count(), 1);
+        assert_eq!(steps[6], vec![[0x99, 49, 100]]);
+        assert_eq!(seq.playhead(), Some(1));
+        assert_eq!(seq.clock(0xFC), vec![[0x89, 49, 0]]);
+    }
+}
@@ -0,0 +1,278 @@
+//! Serde support, with the `serde` feature.
+//!
+//! The representations are meant to be written by hand and to stay stable:
+//!
+//! * RGB colors are `"#rrggbb"`. An `LedColor` is `"off"`, its palette index,
+//!   or an RGB color, and palette names such as `"RED_DIM"` are read too.
+//! * Locations are `{"x": 0, "y": 0}` for pads, counting from the top left as
+//!   in `Frame`, or `{"top": 0}` and `{"side": 0}` for the round buttons.
+//!   Positions in `ColorLed`, `RgbLed` and `Event` are written as locations,
+//!   alongside the other fields: `{"x": 3, "y": 4, "color": 5}`.
+//! * Events are tagged by kind:
+//!   `{"event": "press", "x": 3, "y": 4, "velocity": 90}`, `"release"` or
+//!   `"scroll_finished"`.
+//! * Durations are whole milliseconds.
+
+use std::fmt;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::{Error, Serializer};
+use serde::{Deserialize, Serialize};
+
+use color::{parse_color, LedColor, RGBColor};
+use event::Event;
+use frame::{Location, GRID_SIZE};
+use launchpad::{Color, ColorLed, RgbLed};
+
+impl Serialize for RGBColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("#{:02x}{:02x}{:02x}", self.red(), self.green(), self.blue()))
+    }
+}
+
+impl<'de> Deserialize<'de> for RGBColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RGBColor, D::Error> {
+        match LedColor::deserialize(deserializer)? {
+            LedColor::Rgb(color) => Ok(color),
+            _ => Err(de::Error::custom("expected a color as #rrggbb")),
+        }
+    }
+}
+
+impl Serialize for LedColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            LedColor::Off => serializer.serialize_str("off"),
+            LedColor::Palette(color) => serializer.serialize_u8(color),
+            LedColor::Rgb(ref color) => color.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LedColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<LedColor, D::Error> {
+        deserializer.deserialize_any(LedColorVisitor)
+    }
+}
+
+struct LedColorVisitor;
+
+impl<'de> Visitor<'de> for LedColorVisitor {
+    type Value = LedColor;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"off\", a palette index or name, or #rrggbb")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<LedColor, E> {
+        if value < 128 {
+            Ok(LedColor::Palette(value as Color))
+        } else {
+            Err(E::invalid_value(de::Unexpected::Unsigned(value), &self))
+        }
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<LedColor, E> {
+        if value >= 0 {
+            self.visit_u64(value as u64)
+        } else {
+            Err(E::invalid_value(de::Unexpected::Signed(value), &self))
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<LedColor, E> {
+        if value.eq_ignore_ascii_case("off") {
+            return Ok(LedColor::Off);
+        }
+        parse_color(value).ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+/// How a location is written
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Place {
+    Pad { x: usize, y: usize },
+    Top { top: usize },
+    Side { side: usize },
+}
+
+impl Serialize for Location {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Location::Pad { x, y } => Place::Pad { x, y },
+            Location::Top(top) => Place::Top { top },
+            Location::Side(side) => Place::Side { side },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Location {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Location, D::Error> {
+        let location = match Place::deserialize(deserializer)? {
+            Place::Pad { x, y } if x < GRID_SIZE && y < GRID_SIZE => Location::Pad { x, y },
+            Place::Top { top } if top < GRID_SIZE => Location::Top(top),
+            Place::Side { side } if side < GRID_SIZE => Location::Side(side),
+            _ => return Err(de::Error::custom("location out of range")),
+        };
+        Ok(location)
+    }
+}
+
+fn locate<E: Error>(position: u8) -> Result<Location, E> {
+    Location::of(position).ok_or_else(|| E::custom(format!("no button at position {}", position)))
+}
+
+/// How an LED with a color is written
+#[derive(Serialize, Deserialize)]
+struct Led<C> {
+    #[serde(flatten)]
+    location: Location,
+    color: C,
+}
+
+impl<C: Serialize + Copy> Serialize for ColorLed<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Led {
+                location: locate(self.position)?,
+                color: self.color,
+            }
+            .serialize(serializer)
+    }
+}
+
+impl<'de, C: Deserialize<'de>> Deserialize<'de> for ColorLed<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ColorLed<C>, D::Error> {
+        let led = Led::deserialize(deserializer)?;
+        Ok(ColorLed {
+            color: led.color,
+            position: led.location.position(),
+        })
+    }
+}
+
+impl Serialize for RgbLed {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Led {
+                location: locate(self.position)?,
+                color: self.color,
+            }
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RgbLed {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RgbLed, D::Error> {
+        let led = Led::deserialize(deserializer)?;
+        Ok(RgbLed {
+            color: led.color,
+            position: led.location.position(),
+        })
+    }
+}
+
+/// How an event is written
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Happening {
+    Press {
+        #[serde(flatten)]
+        location: Location,
+        velocity: u8,
+    },
+    Release {
+        #[serde(flatten)]
+        location: Location,
+    },
+    ScrollFinished,
+}
+
+impl Serialize for Event {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Event::Press { position, velocity } => {
+                Happening::Press {
+                    location: locate(position)?,
+                    velocity,
+                }
+            }
+            Event::Release { position } => Happening::Release { location: locate(position)? },
+            Event::ScrollFinished => Happening::ScrollFinished,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Event, D::Error> {
+        Ok(match Happening::deserialize(deserializer)? {
+            Happening::Press { location, velocity } => {
+                Event::Press {
+                    position: location.position(),
+                    velocity,
+                }
+            }
+            Happening::Release { location } => Event::Release { position: location.position() },
+            Happening::ScrollFinished => Event::ScrollFinished,
+        })
+    }
+}
+
+/// Durations as whole milliseconds, for `#[serde(with = "...")]`
+pub(crate) mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, json};
+
+    use super::*;
+    use frame::Frame;
+    use palette;
+
+    #[test]
+    fn representations() {
+        let led = ColorLed {
+            color: palette::RED,
+            position: 62,
+        };
+        assert_eq!(serde_json::to_value(&led).unwrap(), json!({"x": 1, "y": 2, "color": 5}));
+        let led: RgbLed = serde_json::from_value(json!({"side": 0, "color": "#ff8000"})).unwrap();
+        assert_eq!((led.position, led.color), (89, RGBColor::new(255, 128, 0)));
+
+        let event = Event::Press {
+            position: 104,
+            velocity: 90,
+        };
+        let value = serde_json::to_value(event).unwrap();
+        assert_eq!(value, json!({"event": "press", "top": 0, "velocity": 90}));
+        assert_eq!(serde_json::from_value::<Event>(value).unwrap(), event);
+
+        let colors: Vec<LedColor> = serde_json::from_str(r##"["off", 5, "red dim", "#0000FF"]"##).unwrap();
+        assert_eq!(colors,
+                   vec![LedColor::Off,
+                        LedColor::Palette(palette::RED),
+                        LedColor::Palette(palette::RED_DIM),
+                        LedColor::Rgb(RGBColor::new(0, 0, 255))]);
+        assert!(serde_json::from_str::<LedColor>("128").is_err());
+        assert!(serde_json::from_value::<Location>(json!({"x": 8, "y": 0})).is_err());
+
+        let mut frame = Frame::new();
+        frame.set_pad(0, 7, RGBColor::new(1, 2, 3));
+        frame.set_top(3, palette::BLUE);
+        let text = serde_json::to_string(&frame).unwrap();
+        assert_eq!(serde_json::from_str::<Frame>(&text).unwrap(), frame);
+    }
+}
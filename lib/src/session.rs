@@ -0,0 +1,424 @@
+//! Recording and replaying the messages exchanged with a device.
+//!
+//! A `Recorder` captures everything sent to and received from a
+//! `LaunchpadMk2` as a `Session`. Sessions are saved as text, one message per
+//! line: the time in milliseconds since recording started, `out` for messages
+//! sent to the device or `in` for messages from it, and the bytes in hex.
+//!
+//! ```text
+//! # launch-rs session
+//! 0 out F0 00 20 29 02 18 0E 00 F7
+//! 1520 in 90 51 64
+//! 1524 out 90 51 05
+//! 1610 in 90 51 00
+//! ```
+//!
+//! A session can be played back to a device, real or emulated, and the
+//! messages received can be fed to a program again through `Session::input`
+//! to reproduce what a user did.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pm;
+use launchpad::{LaunchpadMk2, MidiInput, MidiOutput};
+
+/// Which way a message went
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Flow {
+    /// Sent to the device
+    Out,
+    /// Received from the device
+    In,
+}
+
+/// A message sent or received during a session
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SessionMessage {
+    /// Milliseconds since the session started
+    pub time: u32,
+    pub flow: Flow,
+    /// Three bytes for a short message, or a whole sysex message. Sysex
+    /// received arrives in pieces, as PortMidi delivers it.
+    pub bytes: Vec<u8>,
+}
+
+impl fmt::Display for SessionMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.time, if self.flow == Flow::Out { "out" } else { "in" })?;
+        for byte in &self.bytes {
+            write!(f, " {:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Messages exchanged with a device, in the order they happened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Session {
+    pub messages: Vec<SessionMessage>,
+}
+
+const HEADER: &str = "# launch-rs session";
+
+impl fmt::Display for Session {
+    /// The session in its file format
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for msg in &self.messages {
+            writeln!(f, "{}", msg)?;
+        }
+        Ok(())
+    }
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    /// Parse a session file. `#` starts a comment.
+    pub fn parse(text: &str) -> Result<Session, SessionError> {
+        let mut session = Session::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = |message: &str| SessionError::Parse {
+                line: i + 1,
+                message: message.to_string(),
+            };
+
+            let mut words = line.split_whitespace();
+            let time = words.next()
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(|| bad("expected a time in milliseconds"))?;
+            let flow = match words.next() {
+                Some("out") => Flow::Out,
+                Some("in") => Flow::In,
+                _ => return Err(bad("expected `out` or `in`")),
+            };
+            let bytes = words.map(|b| u8::from_str_radix(b, 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| bad("expected bytes in hex"))?;
+            if bytes.is_empty() {
+                return Err(bad("expected bytes in hex"));
+            }
+            if session.messages.last().is_some_and(|last| last.time > time) {
+                return Err(bad("time goes backwards"));
+            }
+            session.messages.push(SessionMessage { time, flow, bytes });
+        }
+        Ok(session)
+    }
+
+    /// Load a session file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Session, SessionError> {
+        let text = fs::read_to_string(path).map_err(SessionError::Io)?;
+        Session::parse(&text)
+    }
+
+    /// Save the session to a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Send the messages which went out to a device again, as they were
+    /// timed, blocking until done
+    pub fn play(&self, lpad: &LaunchpadMk2) -> pm::Result<()> {
+        let output = lpad.shared_output();
+        self.play_with(|bytes| send(&mut **output.lock().expect("Output lock poisoned"), bytes))
+    }
+
+    /// Like `play`, sending to any output, e.g. one checking what is sent
+    pub fn play_to(&self, output: &mut dyn MidiOutput) -> pm::Result<()> {
+        self.play_with(|bytes| send(output, bytes))
+    }
+
+    fn play_with<F: FnMut(&[u8]) -> pm::Result<()>>(&self, mut send: F) -> pm::Result<()> {
+        let start = Instant::now();
+        for msg in self.messages.iter().filter(|m| m.flow == Flow::Out) {
+            let due = start + Duration::from_millis(msg.time as u64);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+            send(&msg.bytes)?;
+        }
+        Ok(())
+    }
+
+    /// An input giving the messages which came in again, each once as much
+    /// time has passed since the first read as had in the session. Use it to
+    /// drive a program as a user did, e.g. with
+    /// `LaunchpadMk2::from_ports(session.input(), output)`.
+    pub fn input(&self) -> SessionInput {
+        SessionInput {
+            messages: self.messages
+                .iter()
+                .filter(|m| m.flow == Flow::In && m.bytes.len() == 3)
+                .map(|m| (m.time, [m.bytes[0], m.bytes[1], m.bytes[2]]))
+                .collect(),
+            next: Cell::new(0),
+            start: Cell::new(None),
+        }
+    }
+}
+
+fn send(output: &mut dyn MidiOutput, bytes: &[u8]) -> pm::Result<()> {
+    match *bytes {
+        [status, data1, data2] if status != 0xF0 => output.write_message([status, data1, data2]),
+        _ => output.write_sysex(bytes),
+    }
+}
+
+/// Replays the messages received in a session. See `Session::input`.
+pub struct SessionInput {
+    messages: Vec<(u32, [u8; 3])>,
+    next: Cell<usize>,
+    start: Cell<Option<Instant>>,
+}
+
+impl SessionInput {
+    /// Whether every message has been read
+    pub fn finished(&self) -> bool {
+        self.next.get() >= self.messages.len()
+    }
+}
+
+impl MidiInput for SessionInput {
+    fn read_n(&self, max: usize) -> pm::Result<Option<Vec<pm::MidiEvent>>> {
+        let start = self.start.get().unwrap_or_else(Instant::now);
+        self.start.set(Some(start));
+        let elapsed = start.elapsed();
+
+        let first = self.next.get();
+        let due = self.messages[first..]
+            .iter()
+            .take(max)
+            .take_while(|&&(time, _)| Duration::from_millis(time as u64) <= elapsed)
+            .map(|&(time, msg)| {
+                pm::MidiEvent {
+                    message: msg.into(),
+                    timestamp: time,
+                }
+            })
+            .collect::<Vec<_>>();
+        self.next.set(first + due.len());
+
+        Ok(if due.is_empty() { None } else { Some(due) })
+    }
+}
+
+/// The session being recorded, and where to write it as it grows
+struct Recording {
+    started: Instant,
+    session: Session,
+    out: Option<Box<dyn Write + Send>>,
+    /// A received PortMidi timestamp and the session time it happened at
+    received: Option<(u32, u32)>,
+}
+
+impl Recording {
+    fn now(&self) -> u32 {
+        self.started.elapsed().as_millis() as u32
+    }
+
+    fn record(&mut self, time: u32, flow: Flow, bytes: &[u8]) {
+        let msg = SessionMessage {
+            time,
+            flow,
+            bytes: bytes.to_vec(),
+        };
+        let failed = match self.out {
+            Some(ref mut out) => writeln!(out, "{}", msg).and_then(|_| out.flush()).is_err(),
+            None => false,
+        };
+        if failed {
+            self.out = None;
+        }
+        self.session.messages.push(msg);
+    }
+
+    /// The session time of a received event. PortMidi timestamps received
+    /// messages more precisely than they are read, so their spacing is kept,
+    /// as long as they stay in order and not ahead of now.
+    fn received_time(&mut self, timestamp: u32) -> u32 {
+        let now = self.now();
+        let last = self.session.messages.last().map_or(0, |m| m.time);
+        let time = match self.received {
+            Some((stamp, at)) if timestamp >= stamp && timestamp != 0 => {
+                (at + (timestamp - stamp)).clamp(last, now)
+            }
+            _ => now,
+        };
+        self.received = Some((timestamp, time));
+        time
+    }
+}
+
+/// Records the messages exchanged with a device as a `Session`
+pub struct Recorder {
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl Recorder {
+    /// Start recording everything sent to and received from a device
+    pub fn attach(lpad: &mut LaunchpadMk2) -> Recorder {
+        Recorder::attach_with(lpad, None)
+    }
+
+    /// Like `attach`, also writing each message to `out` in the session file
+    /// format as it happens, so nothing is lost if the program stops
+    /// abruptly. Writing stops at the first error.
+    pub fn attach_writing<W: Write + Send + 'static>(lpad: &mut LaunchpadMk2, mut out: W) -> io::Result<Recorder> {
+        writeln!(out, "{}", HEADER)?;
+        Ok(Recorder::attach_with(lpad, Some(Box::new(out))))
+    }
+
+    fn attach_with(lpad: &mut LaunchpadMk2, out: Option<Box<dyn Write + Send>>) -> Recorder {
+        let recording = Arc::new(Mutex::new(Recording {
+            started: Instant::now(),
+            session: Session::new(),
+            out,
+            received: None,
+        }));
+        let (input, output) = (recording.clone(), recording.clone());
+        lpad.wrap_ports(|inner| {
+                            Box::new(RecordingInput {
+                                inner,
+                                recording: input,
+                            })
+                        },
+                        |inner| {
+                            Box::new(RecordingOutput {
+                                inner,
+                                recording: output,
+                            })
+                        });
+        Recorder { recording }
+    }
+
+    /// What has been recorded so far
+    pub fn session(&self) -> Session {
+        self.recording.lock().expect("Recording lock poisoned").session.clone()
+    }
+}
+
+struct RecordingOutput {
+    inner: Box<dyn MidiOutput>,
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl MidiOutput for RecordingOutput {
+    fn write_message(&mut self, msg: [u8; 3]) -> pm::Result<()> {
+        self.inner.write_message(msg)?;
+        let mut recording = self.recording.lock().expect("Recording lock poisoned");
+        let now = recording.now();
+        recording.record(now, Flow::Out, &msg);
+        Ok(())
+    }
+
+    fn write_sysex(&mut self, msg: &[u8]) -> pm::Result<()> {
+        self.inner.write_sysex(msg)?;
+        let mut recording = self.recording.lock().expect("Recording lock poisoned");
+        let now = recording.now();
+        recording.record(now, Flow::Out, msg);
+        Ok(())
+    }
+}
+
+struct RecordingInput {
+    inner: Box<dyn MidiInput>,
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl MidiInput for RecordingInput {
+    fn read_n(&self, max: usize) -> pm::Result<Option<Vec<pm::MidiEvent>>> {
+        let read = self.inner.read_n(max)?;
+        let mut recording = self.recording.lock().expect("Recording lock poisoned");
+        for event in read.iter().flatten() {
+            let msg = event.message;
+            let time = recording.received_time(event.timestamp);
+            recording.record(time, Flow::In, &[msg.status, msg.data1, msg.data2]);
+        }
+        Ok(read)
+    }
+}
+
+/// Failure to load a session
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionError::Io(ref err) => write!(f, "{}", err),
+            SessionError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for SessionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emulator::Emulator;
+    use frame::Location;
+    use palette;
+
+    #[test]
+    fn record_and_replay() {
+        let (emulator, mut lpad) = Emulator::new();
+        let recorder = Recorder::attach(&mut lpad);
+        lpad.set_all(palette::RED);
+        emulator.press(Location::Pad { x: 1, y: 2 }, 90);
+        lpad.poll_events();
+        lpad.set_led(62, palette::BLUE);
+
+        let session = Session::parse(&recorder.session().to_string()).unwrap();
+        assert_eq!(session, recorder.session());
+        assert_eq!(session.messages.iter().map(|m| m.flow).collect::<Vec<_>>(),
+                   vec![Flow::Out, Flow::In, Flow::Out]);
+
+        let (replayed, replay) = Emulator::new();
+        session.play(&replay).unwrap();
+        assert_eq!(replayed.screen().led(62), emulator.screen().led(62));
+        assert_eq!(replayed.screen().led(11), emulator.screen().led(11));
+
+        let input = session.input();
+        let mut events = Vec::new();
+        while events.is_empty() {
+            events = input.read_n(16).unwrap().unwrap_or_default();
+        }
+        assert_eq!(events[0].message, pm::MidiMessage::from([0x90, 62, 90]));
+        assert!(input.finished());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Session::parse("0 out 90 0B 05\n# done\n").is_ok());
+        match Session::parse("5 out 90\n2 in 90 0B 00\n") {
+            Err(SessionError::Parse { line: 2, .. }) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(Session::parse("0 sideways 90").is_err());
+        assert!(Session::parse("0 out 9G").is_err());
+    }
+}
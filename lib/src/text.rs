@@ -0,0 +1,416 @@
+//! Software text rendering with a built-in bitmap font.
+//!
+//! Unlike the device's `scroll_text`, text drawn here can use any color per
+//! character, scroll in any direction at any speed, and only touches the pads,
+//! leaving the top and side buttons alone. A `Scroller` is advanced from the
+//! application's own loop, so input can be handled between frames.
+
+use std::time::Duration;
+
+use color::LedColor;
+use frame::{Frame, GRID_SIZE};
+
+/// Width of each glyph in `FONT`
+pub const GLYPH_WIDTH: usize = 5;
+/// Height of each glyph in `FONT`
+pub const GLYPH_HEIGHT: usize = 7;
+
+/// 5x7 glyphs for printable ASCII (0x20...0x7E). Each byte is a column, left
+/// to right, with the least significant bit the top row.
+pub const FONT: [[u8; GLYPH_WIDTH]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x14, 0x08, 0x3e, 0x08, 0x14], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // f
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // j
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+/// Glyph used for characters missing from `FONT`
+const MISSING: char = '?';
+
+/// The columns of a character's glyph
+pub fn glyph(c: char) -> &'static [u8; GLYPH_WIDTH] {
+    let index = match c {
+        ' '..='~' => c as usize - 0x20,
+        _ => MISSING as usize - 0x20,
+    };
+    &FONT[index]
+}
+
+/// A string where every character has its own color
+#[derive(Debug, Clone, Default)]
+pub struct Text {
+    chars: Vec<(char, LedColor)>,
+}
+
+impl Text {
+    /// Empty text
+    pub fn new() -> Text {
+        Text::default()
+    }
+
+    /// Add characters in a single color. Characters the font does not have
+    /// are drawn as `?`.
+    pub fn push<C: Into<LedColor>>(mut self, text: &str, color: C) -> Text {
+        let color = color.into();
+        self.chars.extend(text.chars().map(|c| (c, color)));
+        self
+    }
+
+    /// Add characters, cycling through the colors for each one
+    pub fn push_cycle(mut self, text: &str, colors: &[LedColor]) -> Text {
+        assert!(!colors.is_empty());
+        self.chars.extend(text.chars().zip(colors.iter().cycle().cloned()));
+        self
+    }
+
+    /// The characters and their colors
+    pub fn chars(&self) -> &[(char, LedColor)] {
+        &self.chars
+    }
+
+    /// Render the text on one line, as columns of pads from left to right.
+    /// Glyphs sit on the bottom row with one blank column between them.
+    pub fn columns(&self, background: LedColor) -> Vec<[LedColor; GRID_SIZE]> {
+        let top = GRID_SIZE - GLYPH_HEIGHT;
+        let mut columns = Vec::with_capacity(self.chars.len() * (GLYPH_WIDTH + 1));
+        for &(c, color) in &self.chars {
+            for &bits in glyph(c).iter() {
+                let mut column = [background; GRID_SIZE];
+                for (row, pad) in column[top..].iter_mut().enumerate() {
+                    if bits & (1 << row) != 0 {
+                        *pad = color;
+                    }
+                }
+                columns.push(column);
+            }
+            columns.push([background; GRID_SIZE]);
+        }
+        columns
+    }
+
+    /// Render the text with one character per line, as rows of pads from top
+    /// to bottom. Glyphs are centered with one blank row between them.
+    pub fn rows(&self, background: LedColor) -> Vec<[LedColor; GRID_SIZE]> {
+        let left = (GRID_SIZE - GLYPH_WIDTH) / 2;
+        let mut rows = Vec::with_capacity(self.chars.len() * (GLYPH_HEIGHT + 1));
+        for &(c, color) in &self.chars {
+            let columns = glyph(c);
+            for row in 0..GLYPH_HEIGHT {
+                let mut line = [background; GRID_SIZE];
+                for (pad, &bits) in line[left..].iter_mut().zip(columns.iter()) {
+                    if bits & (1 << row) != 0 {
+                        *pad = color;
+                    }
+                }
+                rows.push(line);
+            }
+            rows.push([background; GRID_SIZE]);
+        }
+        rows
+    }
+
+    /// Draw the text on one line with its left edge at column `x`, which may
+    /// be negative or past the grid to draw only part of it. Only lit pixels
+    /// are drawn.
+    pub fn draw(&self, frame: &mut Frame, x: isize) {
+        for (i, column) in self.columns(LedColor::Off).iter().enumerate() {
+            let col = x + i as isize;
+            if col < 0 || col >= GRID_SIZE as isize {
+                continue;
+            }
+            for (y, &color) in column.iter().enumerate() {
+                if color != LedColor::Off {
+                    frame.set_pad(col as usize, y, color);
+                }
+            }
+        }
+    }
+}
+
+/// Which way scrolling text moves
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Direction {
+    /// Enters on the right, like a news ticker
+    Left,
+    /// Enters on the left
+    Right,
+    /// One character per line, entering at the bottom
+    Up,
+    /// One character per line, entering at the top
+    Down,
+}
+
+/// Scrolls `Text` across the pads. Call `advance` with the time passed and
+/// `draw` the result into a frame whenever it changes.
+#[derive(Debug, Clone)]
+pub struct Scroller {
+    lines: Vec<[LedColor; GRID_SIZE]>,
+    direction: Direction,
+    speed: f32,
+    offset: f32,
+    looping: bool,
+    background: LedColor,
+}
+
+impl Scroller {
+    /// Scroll text in a direction at `speed` pads per second. The text starts
+    /// just off the grid and scrolls until it has left it completely.
+    pub fn new(text: &Text, direction: Direction, speed: f32) -> Scroller {
+        Scroller::with_background(text, direction, speed, LedColor::Off)
+    }
+
+    /// Like `new`, with the pads not covered by text set to `background`
+    pub fn with_background(text: &Text,
+                           direction: Direction,
+                           speed: f32,
+                           background: LedColor)
+                           -> Scroller {
+        let mut lines = match direction {
+            Direction::Left | Direction::Right => text.columns(background),
+            Direction::Up | Direction::Down => text.rows(background),
+        };
+        if let Direction::Right | Direction::Down = direction {
+            lines.reverse();
+        }
+
+        Scroller {
+            lines,
+            direction,
+            speed,
+            offset: 0.0,
+            looping: false,
+            background,
+        }
+    }
+
+    /// Start over once the text has scrolled past, instead of finishing
+    pub fn looping(mut self, looping: bool) -> Scroller {
+        self.looping = looping;
+        self
+    }
+
+    /// Change the speed, in pads per second
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// Length of the scroll, from the text entering until it starts again
+    fn period(&self) -> usize {
+        self.lines.len() + GRID_SIZE
+    }
+
+    /// Whether a non-looping scroll has moved the text off the grid
+    pub fn finished(&self) -> bool {
+        !self.looping && self.offset as usize >= self.period()
+    }
+
+    /// Move the text along for the time passed. Returns whether anything on
+    /// the grid moved, and so needs drawing again.
+    pub fn advance(&mut self, elapsed: Duration) -> bool {
+        if self.finished() {
+            return false;
+        }
+        let before = self.offset as usize;
+        self.offset += self.speed * elapsed.as_secs_f32();
+        if self.looping {
+            self.offset %= self.period() as f32;
+        } else {
+            self.offset = self.offset.min(self.period() as f32);
+        }
+        self.offset as usize != before
+    }
+
+    /// Draw the visible part of the text onto the pads of a frame
+    pub fn draw(&self, frame: &mut Frame) {
+        let offset = self.offset as usize;
+        let period = self.period();
+        for i in 0..GRID_SIZE {
+            // The text is preceded by a grid's width of blank lines
+            let index = (offset + i) % period.max(1);
+            let line = if index >= GRID_SIZE && (offset + i < period || self.looping) {
+                self.lines[index - GRID_SIZE]
+            } else {
+                [self.background; GRID_SIZE]
+            };
+
+            for (j, &color) in line.iter().enumerate() {
+                match self.direction {
+                    Direction::Left => frame.set_pad(i, j, color),
+                    Direction::Right => frame.set_pad(GRID_SIZE - 1 - i, j, color),
+                    Direction::Up => frame.set_pad(j, i, color),
+                    Direction::Down => frame.set_pad(j, GRID_SIZE - 1 - i, color),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scroll_left_enters_from_the_right() {
+        let text = Text::new().push("I", 5);
+        let mut scroller = Scroller::new(&text, Direction::Left, 1.0);
+        let mut frame = Frame::new();
+
+        assert!(scroller.advance(Duration::from_secs(3)));
+        scroller.draw(&mut frame);
+        // The glyph's third column is a full bar, now at the right edge
+        assert_eq!(frame.pad(GRID_SIZE - 1, 0), LedColor::Off);
+        assert!((1..GRID_SIZE).all(|y| frame.pad(GRID_SIZE - 1, y) == LedColor::Palette(5)));
+
+        assert!(!scroller.finished());
+        scroller.advance(Duration::from_secs(60));
+        assert!(scroller.finished());
+    }
+
+    fn lit(scroller: &Scroller) -> Vec<(usize, usize)> {
+        let mut frame = Frame::new();
+        scroller.draw(&mut frame);
+        let mut lit = Vec::new();
+        for y in 0..GRID_SIZE {
+            for x in 0..GRID_SIZE {
+                if frame.pad(x, y) != LedColor::Off {
+                    lit.push((x, y));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn vertical_scrolls() {
+        // A bar seven rows high in the middle column, then a blank row
+        let text = Text::new().push("|", 5);
+
+        let mut up = Scroller::new(&text, Direction::Up, 1.0);
+        up.advance(Duration::from_secs(2));
+        assert_eq!(lit(&up), vec![(3, 6), (3, 7)]);
+        up.advance(Duration::from_secs(6));
+        assert_eq!(lit(&up), (0..7).map(|y| (3, y)).collect::<Vec<_>>());
+
+        // Going down, the blank row enters first
+        let mut down = Scroller::new(&text, Direction::Down, 1.0);
+        down.advance(Duration::from_secs(2));
+        assert_eq!(lit(&down), vec![(3, 0)]);
+        down.advance(Duration::from_secs(60));
+        assert!(down.finished());
+        assert_eq!(lit(&down), vec![]);
+    }
+
+    #[test]
+    fn looping_wraps_around() {
+        let text = Text::new().push("|", 5);
+        // Six columns of text after eight blank ones
+        let mut scroller = Scroller::new(&text, Direction::Left, 1.0).looping(true);
+        scroller.advance(Duration::from_secs(14));
+        assert_eq!(lit(&scroller), vec![]);
+
+        // Ten pads into a later pass, the bar is at the left edge
+        scroller.advance(Duration::from_secs(14 * 10 + 10));
+        assert!(!scroller.finished());
+        assert_eq!(lit(&scroller), (1..8).map(|y| (0, y)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn push_cycle_alternates_colors() {
+        let colors = [LedColor::Palette(5), LedColor::Palette(9)];
+        let text = Text::new().push_cycle("abc", &colors);
+        assert_eq!(text.chars(), &[('a', colors[0]), ('b', colors[1]), ('c', colors[0])]);
+    }
+}
@@ -0,0 +1,589 @@
+//! Control surface widgets.
+//!
+//! Each widget owns some of the device's LEDs, turns presses on them into
+//! `WidgetEvent`s and draws its own state. A `Panel` holds widgets, routes
+//! decoded input to them, calls their callbacks and keeps the device lit.
+
+use std::any::Any;
+
+use color::LedColor;
+use event::Event;
+use frame::{Frame, Location, GRID_SIZE};
+use launchpad::LaunchpadMk2;
+
+/// A change in a widget's state
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum WidgetEvent {
+    /// A momentary button went down
+    Pressed,
+    /// A momentary button came back up
+    Released,
+    /// A toggle was switched on or off
+    Toggled(bool),
+    /// A radio group option was chosen, by index
+    Selected(usize),
+    /// An XY pad was touched, `(0, 0)` being its top left
+    Moved { x: usize, y: usize },
+    /// A slider was set, from 0 (empty) to its length (full)
+    Value(usize),
+}
+
+/// Something which owns LEDs, reacts to presses on them and draws itself
+pub trait Widget {
+    /// Whether the LED at a location belongs to this widget
+    fn contains(&self, location: Location) -> bool;
+
+    /// React to an event on one of this widget's LEDs, returning how the
+    /// widget's state changed, if it did
+    fn handle(&mut self, location: Location, event: &Event) -> Option<WidgetEvent>;
+
+    /// Draw the widget's state
+    fn draw(&self, frame: &mut Frame);
+}
+
+/// LED colors for a widget's two states
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Colors {
+    pub on: LedColor,
+    pub off: LedColor,
+}
+
+impl Colors {
+    pub fn new<A: Into<LedColor>, B: Into<LedColor>>(on: A, off: B) -> Colors {
+        Colors {
+            on: on.into(),
+            off: off.into(),
+        }
+    }
+}
+
+/// A button which is on while held down
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Button {
+    pub location: Location,
+    pub colors: Colors,
+    pub pressed: bool,
+}
+
+impl Button {
+    pub fn new(location: Location, colors: Colors) -> Button {
+        Button {
+            location,
+            colors,
+            pressed: false,
+        }
+    }
+}
+
+impl Widget for Button {
+    fn contains(&self, location: Location) -> bool {
+        location == self.location
+    }
+
+    fn handle(&mut self, _: Location, event: &Event) -> Option<WidgetEvent> {
+        match *event {
+            Event::Press { .. } if !self.pressed => {
+                self.pressed = true;
+                Some(WidgetEvent::Pressed)
+            }
+            Event::Release { .. } if self.pressed => {
+                self.pressed = false;
+                Some(WidgetEvent::Released)
+            }
+            _ => None,
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        frame.set(self.location, if self.pressed { self.colors.on } else { self.colors.off });
+    }
+}
+
+/// A button which switches on or off with each press
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Toggle {
+    pub location: Location,
+    pub colors: Colors,
+    pub on: bool,
+}
+
+impl Toggle {
+    pub fn new(location: Location, colors: Colors) -> Toggle {
+        Toggle {
+            location,
+            colors,
+            on: false,
+        }
+    }
+}
+
+impl Widget for Toggle {
+    fn contains(&self, location: Location) -> bool {
+        location == self.location
+    }
+
+    fn handle(&mut self, _: Location, event: &Event) -> Option<WidgetEvent> {
+        match *event {
+            Event::Press { .. } => {
+                self.on = !self.on;
+                Some(WidgetEvent::Toggled(self.on))
+            }
+            _ => None,
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        frame.set(self.location, if self.on { self.colors.on } else { self.colors.off });
+    }
+}
+
+/// Buttons of which exactly one is chosen at a time
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RadioGroup {
+    pub locations: Vec<Location>,
+    pub colors: Colors,
+    pub selected: usize,
+}
+
+impl RadioGroup {
+    pub fn new(locations: Vec<Location>, colors: Colors) -> RadioGroup {
+        assert!(!locations.is_empty());
+        RadioGroup {
+            locations,
+            colors,
+            selected: 0,
+        }
+    }
+}
+
+impl Widget for RadioGroup {
+    fn contains(&self, location: Location) -> bool {
+        self.locations.contains(&location)
+    }
+
+    fn handle(&mut self, location: Location, event: &Event) -> Option<WidgetEvent> {
+        let index = self.locations.iter().position(|&l| l == location)?;
+        match *event {
+            Event::Press { .. } if index != self.selected => {
+                self.selected = index;
+                Some(WidgetEvent::Selected(index))
+            }
+            _ => None,
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        for (i, &location) in self.locations.iter().enumerate() {
+            frame.set(location, if i == self.selected { self.colors.on } else { self.colors.off });
+        }
+    }
+}
+
+/// A rectangle of pads where touching a pad picks a point, shown as a
+/// crosshair. Pads off the grid are left out.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct XYPad {
+    /// Top left pad
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub colors: Colors,
+    /// The point picked, relative to the top left pad
+    pub point: Option<(usize, usize)>,
+}
+
+impl XYPad {
+    /// An XY pad with its top left pad at `(x, y)`, cut down to the part of it
+    /// on the grid
+    pub fn new(x: usize, y: usize, width: usize, height: usize, colors: Colors) -> XYPad {
+        let (x, y) = (x.min(GRID_SIZE), y.min(GRID_SIZE));
+        XYPad {
+            x,
+            y,
+            width: width.min(GRID_SIZE - x),
+            height: height.min(GRID_SIZE - y),
+            colors,
+            point: None,
+        }
+    }
+}
+
+impl Widget for XYPad {
+    fn contains(&self, location: Location) -> bool {
+        match location {
+            Location::Pad { x, y } => {
+                x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+            }
+            _ => false,
+        }
+    }
+
+    fn handle(&mut self, location: Location, event: &Event) -> Option<WidgetEvent> {
+        match (location, *event) {
+            (Location::Pad { x, y }, Event::Press { .. }) if self.contains(location) => {
+                let point = (x - self.x, y - self.y);
+                self.point = Some(point);
+                Some(WidgetEvent::Moved {
+                    x: point.0,
+                    y: point.1,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        for y in 0..self.height.min(GRID_SIZE.saturating_sub(self.y)) {
+            for x in 0..self.width.min(GRID_SIZE.saturating_sub(self.x)) {
+                let on = self.point.is_some_and(|(px, py)| px == x || py == y);
+                frame.set_pad(self.x + x, self.y + y, if on { self.colors.on } else { self.colors.off });
+            }
+        }
+    }
+}
+
+/// Which way a slider runs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Orientation {
+    /// A column of pads, filling up from the bottom
+    Vertical,
+    /// A row of pads, filling from the left
+    Horizontal,
+}
+
+/// A fader made of a column or row of pads. Pressing a pad sets the value
+/// to fill up to it; pressing the first pad again while the value is 1 sets
+/// it to 0. Pads off the grid are left out.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Slider {
+    pub orientation: Orientation,
+    /// Column of a vertical slider, or row of a horizontal one
+    pub index: usize,
+    /// First pad along the slider: the bottom row of a vertical slider counts
+    /// as 0, the left column of a horizontal one
+    pub start: usize,
+    pub length: usize,
+    pub colors: Colors,
+    pub value: usize,
+}
+
+impl Slider {
+    /// A slider, cut down to the part of it on the grid
+    pub fn new(orientation: Orientation, index: usize, start: usize, length: usize, colors: Colors) -> Slider {
+        let length = if index < GRID_SIZE { length.min(GRID_SIZE.saturating_sub(start)) } else { 0 };
+        Slider {
+            orientation,
+            index,
+            start,
+            length,
+            colors,
+            value: 0,
+        }
+    }
+
+    /// The pad at a step along the slider, 0 being the start, if it is on
+    /// the grid
+    fn pad(&self, step: usize) -> Option<(usize, usize)> {
+        let along = self.start.checked_add(step).filter(|&along| along < GRID_SIZE)?;
+        if self.index >= GRID_SIZE {
+            return None;
+        }
+        Some(match self.orientation {
+            Orientation::Vertical => (self.index, GRID_SIZE - 1 - along),
+            Orientation::Horizontal => (along, self.index),
+        })
+    }
+
+    /// The steps along the slider which are on the grid, with their pads
+    fn pads(&self) -> impl Iterator<Item = (usize, (usize, usize))> + '_ {
+        (0..self.length).map_while(move |step| self.pad(step).map(|pad| (step, pad)))
+    }
+
+    /// The step along the slider of a location, if it is on the slider
+    fn step(&self, location: Location) -> Option<usize> {
+        self.pads().find(|&(_, (x, y))| location == Location::Pad { x, y }).map(|(step, _)| step)
+    }
+}
+
+impl Widget for Slider {
+    fn contains(&self, location: Location) -> bool {
+        self.step(location).is_some()
+    }
+
+    fn handle(&mut self, location: Location, event: &Event) -> Option<WidgetEvent> {
+        let step = self.step(location)?;
+        match *event {
+            Event::Press { .. } => {
+                let value = if step == 0 && self.value == 1 { 0 } else { step + 1 };
+                self.value = value;
+                Some(WidgetEvent::Value(value))
+            }
+            _ => None,
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        for (step, (x, y)) in self.pads() {
+            frame.set_pad(x, y, if step < self.value { self.colors.on } else { self.colors.off });
+        }
+    }
+}
+
+/// Identifies a widget added to a `Panel`
+pub type WidgetId = usize;
+
+/// Called with a widget's events
+type Callback = Box<dyn FnMut(WidgetEvent)>;
+
+/// A widget which can be handed back as its own type
+trait AnyWidget: Widget {
+    fn as_widget(&self) -> &dyn Widget;
+    fn as_widget_mut(&mut self) -> &mut dyn Widget;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<W: Widget + 'static> AnyWidget for W {
+    fn as_widget(&self) -> &dyn Widget {
+        self
+    }
+
+    fn as_widget_mut(&mut self) -> &mut dyn Widget {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A set of widgets sharing the device
+pub struct Panel {
+    widgets: Vec<(Box<dyn AnyWidget>, Option<Callback>)>,
+    shown: Option<Frame>,
+}
+
+impl Default for Panel {
+    fn default() -> Panel {
+        Panel::new()
+    }
+}
+
+impl Panel {
+    pub fn new() -> Panel {
+        Panel {
+            widgets: Vec::new(),
+            shown: None,
+        }
+    }
+
+    /// Add a widget. Widgets added later are drawn over earlier ones, and get
+    /// input first where they overlap.
+    pub fn add<W: Widget + 'static>(&mut self, widget: W) -> WidgetId {
+        self.widgets.push((Box::new(widget), None));
+        self.widgets.len() - 1
+    }
+
+    /// Add a widget with a callback for its events
+    pub fn add_with<W, F>(&mut self, widget: W, callback: F) -> WidgetId
+        where W: Widget + 'static,
+              F: FnMut(WidgetEvent) + 'static
+    {
+        self.widgets.push((Box::new(widget), Some(Box::new(callback))));
+        self.widgets.len() - 1
+    }
+
+    /// A widget added to the panel
+    pub fn widget(&self, id: WidgetId) -> &dyn Widget {
+        self.widgets[id].0.as_widget()
+    }
+
+    /// A widget added to the panel, to change its state
+    pub fn widget_mut(&mut self, id: WidgetId) -> &mut dyn Widget {
+        self.widgets[id].0.as_widget_mut()
+    }
+
+    /// A widget added to the panel as its own type, e.g. to read a
+    /// `Slider`'s value. `None` if the widget is of another type.
+    pub fn widget_as<W: Widget + 'static>(&self, id: WidgetId) -> Option<&W> {
+        self.widgets[id].0.as_any().downcast_ref()
+    }
+
+    /// A widget added to the panel as its own type, to change its state
+    pub fn widget_as_mut<W: Widget + 'static>(&mut self, id: WidgetId) -> Option<&mut W> {
+        self.widgets[id].0.as_any_mut().downcast_mut()
+    }
+
+    /// Route an event to the topmost widget containing its LED, calling its
+    /// callback. Returns the widget and how it changed, if it did.
+    pub fn handle(&mut self, event: &Event) -> Option<(WidgetId, WidgetEvent)> {
+        let position = match *event {
+            Event::Press { position, .. } | Event::Release { position } => position,
+            _ => return None,
+        };
+        let location = Location::of(position)?;
+
+        let id = self.widgets.iter().rposition(|w| w.0.contains(location))?;
+        let (widget, callback) = &mut self.widgets[id];
+        let change = widget.handle(location, event)?;
+        if let Some(callback) = callback {
+            callback(change);
+        }
+        Some((id, change))
+    }
+
+    /// Draw every widget
+    pub fn draw(&self, frame: &mut Frame) {
+        for (widget, _) in &self.widgets {
+            widget.draw(frame);
+        }
+    }
+
+    /// Handle pending input from the device and light the LEDs which
+    /// changed. Call this regularly from the application's loop.
+    pub fn update(&mut self, lpad: &mut LaunchpadMk2) -> Vec<(WidgetId, WidgetEvent)> {
+        let changes = lpad.poll_events().iter().filter_map(|e| self.handle(e)).collect();
+
+        let mut frame = self.shown.clone().unwrap_or_default();
+        self.draw(&mut frame);
+        match self.shown {
+            Some(ref shown) => lpad.draw_frame_diff(&frame, shown),
+            None => lpad.draw_frame(&frame),
+        }
+        self.shown = Some(frame);
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame::pad_position;
+    use palette;
+
+    fn colors() -> Colors {
+        Colors::new(palette::RED, LedColor::Off)
+    }
+
+    fn press(x: usize, y: usize) -> Event {
+        Event::Press {
+            position: pad_position(x, y),
+            velocity: 127,
+        }
+    }
+
+    fn release(x: usize, y: usize) -> Event {
+        Event::Release { position: pad_position(x, y) }
+    }
+
+    /// The pads lit in the `on` color
+    fn lit<W: Widget>(widget: &W) -> Vec<(usize, usize)> {
+        let mut frame = Frame::new();
+        widget.draw(&mut frame);
+        let mut lit = Vec::new();
+        for y in 0..GRID_SIZE {
+            for x in 0..GRID_SIZE {
+                if frame.pad(x, y) == LedColor::Palette(palette::RED) {
+                    lit.push((x, y));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn buttons() {
+        let location = Location::Pad { x: 1, y: 2 };
+        let mut button = Button::new(location, colors());
+        assert_eq!(button.handle(location, &press(1, 2)), Some(WidgetEvent::Pressed));
+        assert_eq!(lit(&button), vec![(1, 2)]);
+        assert_eq!(button.handle(location, &press(1, 2)), None);
+        assert_eq!(button.handle(location, &release(1, 2)), Some(WidgetEvent::Released));
+        assert!(lit(&button).is_empty());
+
+        let mut toggle = Toggle::new(location, colors());
+        assert_eq!(toggle.handle(location, &press(1, 2)), Some(WidgetEvent::Toggled(true)));
+        assert_eq!(toggle.handle(location, &release(1, 2)), None);
+        assert_eq!(lit(&toggle), vec![(1, 2)]);
+        assert_eq!(toggle.handle(location, &press(1, 2)), Some(WidgetEvent::Toggled(false)));
+        assert!(lit(&toggle).is_empty());
+    }
+
+    #[test]
+    fn radio_group() {
+        let locations: Vec<_> = (0..3).map(|x| Location::Pad { x, y: 0 }).collect();
+        let mut radio = RadioGroup::new(locations.clone(), colors());
+        assert_eq!(lit(&radio), vec![(0, 0)]);
+        assert_eq!(radio.handle(locations[2], &press(2, 0)), Some(WidgetEvent::Selected(2)));
+        assert_eq!(radio.handle(locations[2], &press(2, 0)), None);
+        assert_eq!(lit(&radio), vec![(2, 0)]);
+    }
+
+    #[test]
+    fn xy_pad() {
+        let mut pad = XYPad::new(6, 6, 4, 4, colors());
+        assert_eq!((pad.width, pad.height), (2, 2));
+        assert!(!pad.contains(Location::Pad { x: 5, y: 6 }));
+        let location = Location::Pad { x: 7, y: 6 };
+        assert_eq!(pad.handle(location, &press(7, 6)), Some(WidgetEvent::Moved { x: 1, y: 0 }));
+        assert_eq!(lit(&pad), vec![(6, 6), (7, 6), (7, 7)]);
+
+        // Out of range values, e.g. deserialized, are left off the grid too
+        pad.width = 100;
+        pad.x = usize::MAX;
+        assert!(!pad.contains(Location::Pad { x: 7, y: 6 }));
+        assert!(lit(&pad).is_empty());
+    }
+
+    #[test]
+    fn slider() {
+        let mut slider = Slider::new(Orientation::Vertical, 2, 5, 6, colors());
+        assert_eq!(slider.length, 3);
+        assert!(slider.contains(Location::Pad { x: 2, y: 0 }));
+        assert!(!slider.contains(Location::Pad { x: 2, y: 3 }));
+        assert_eq!(slider.handle(Location::Pad { x: 2, y: 1 }, &press(2, 1)),
+                   Some(WidgetEvent::Value(2)));
+        assert_eq!(lit(&slider), vec![(2, 1), (2, 2)]);
+        assert_eq!(slider.handle(Location::Pad { x: 2, y: 2 }, &press(2, 2)),
+                   Some(WidgetEvent::Value(1)));
+        assert_eq!(slider.handle(Location::Pad { x: 2, y: 2 }, &press(2, 2)),
+                   Some(WidgetEvent::Value(0)));
+
+        let mut slider = Slider::new(Orientation::Horizontal, 8, 0, 8, colors());
+        assert_eq!(slider.length, 0);
+        slider.length = 8;
+        slider.value = 8;
+        assert!(lit(&slider).is_empty());
+    }
+
+    #[test]
+    fn panel() {
+        let mut panel = Panel::new();
+        let slider = panel.add(Slider::new(Orientation::Horizontal, 0, 0, 8, colors()));
+        let toggle = panel.add(Toggle::new(Location::Pad { x: 0, y: 0 }, colors()));
+
+        // The toggle was added last, so it gets the press
+        assert_eq!(panel.handle(&press(0, 0)), Some((toggle, WidgetEvent::Toggled(true))));
+        assert_eq!(panel.handle(&press(3, 0)), Some((slider, WidgetEvent::Value(4))));
+        assert_eq!(panel.widget_as::<Slider>(slider).map(|s| s.value), Some(4));
+        assert!(panel.widget_as::<Slider>(toggle).is_none());
+        panel.widget_as_mut::<Toggle>(toggle).unwrap().on = false;
+        assert!(!panel.widget_as::<Toggle>(toggle).unwrap().on);
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Arg, App};
+use clap::{Arg, App, SubCommand};
 
 pub fn build_args() -> App<'static, 'static> {
     App::new("launch-rs")
@@ -8,4 +8,131 @@ pub fn build_args() -> App<'static, 'static> {
             .short("l")
             .long("list")
             .help("List available devices"))
+        .arg(Arg::with_name("json")
+            .long("json")
+            .requires("list")
+            .help("Print the device list as JSON"))
+        .arg(Arg::with_name("emulate")
+            .long("emulate")
+            .global(true)
+            .help("Drive a Launchpad emulated in the terminal instead of a real one"))
+        .arg(Arg::with_name("mirror")
+            .long("mirror")
+            .global(true)
+            .help("Show what the Launchpad has been sent in the terminal"))
+        .arg(Arg::with_name("record")
+            .long("record")
+            .takes_value(true)
+            .global(true)
+            .help("Record everything sent to and received from the Launchpad to a session file"))
+        .subcommand(SubCommand::with_name("image")
+            .about("Display a PNG or PPM image on the grid")
+            .args(&image_args()))
+        .subcommand(SubCommand::with_name("gif")
+            .about("Play an animated GIF on the grid")
+            .args(&image_args())
+            .arg(Arg::with_name("loops")
+                .long("loops")
+                .takes_value(true)
+                .help("Number of times to play the animation, 0 for forever. \
+                       Defaults to the setting in the file")))
+        .subcommand(SubCommand::with_name("scene")
+            .about("Light the device as described in a TOML or JSON scene file")
+            .arg(Arg::with_name("file")
+                .required(true)
+                .help("Scene file, read as JSON if it ends in .json and as TOML otherwise")))
+        .subcommand(SubCommand::with_name("seq")
+            .about("Step sequencer: rows are tracks, columns are steps, top buttons pick \
+                    the page of steps shown and side buttons mute tracks. Enter quits, \
+                    sending note offs for anything still sounding")
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .required(true)
+                .help("Id of the MIDI output to send notes to, as shown by --list"))
+            .arg(Arg::with_name("bpm")
+                .long("bpm")
+                .takes_value(true)
+                .default_value("120")
+                .help("Tempo, in quarter notes per minute"))
+            .arg(Arg::with_name("clock")
+                .long("clock")
+                .takes_value(true)
+                .help("Id of a MIDI input to follow the clock of, ignoring --bpm"))
+            .arg(Arg::with_name("steps")
+                .long("steps")
+                .takes_value(true)
+                .possible_values(&["8", "16", "32", "64"])
+                .default_value("16")
+                .help("Length of the pattern, in sixteenth notes")))
+        .subcommand(SubCommand::with_name("map")
+            .about("Send notes, controls and program changes from the buttons to another \
+                    MIDI output, as set out in a mapping file. Enter quits, releasing any \
+                    notes and controls still on")
+            .arg(Arg::with_name("config")
+                .required(true)
+                .help("Mapping file, one button per line, e.g. `pad 0 7 note 36 channel 10 \
+                       toggle on RED off RED_DARK`"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .required(true)
+                .help("Id of the MIDI output to send to, as shown by --list")))
+        .subcommand(SubCommand::with_name("osc")
+            .about("Light the device from OSC messages and send its button presses as OSC")
+            .arg(Arg::with_name("listen")
+                .long("listen")
+                .takes_value(true)
+                .default_value("127.0.0.1:8000")
+                .help("UDP address to receive messages on"))
+            .arg(Arg::with_name("target")
+                .long("target")
+                .takes_value(true)
+                .default_value("127.0.0.1:9000")
+                .help("UDP address to send presses and releases to")))
+        .subcommand(SubCommand::with_name("serve")
+            .about("Serve the device over HTTP and WebSocket: GET or POST /frame, POST \
+                    /commands, and subscribe to button events at /events")
+            .arg(Arg::with_name("listen")
+                .long("listen")
+                .takes_value(true)
+                .default_value("127.0.0.1:8080")
+                .help("Address to listen on")))
+        .subcommand(SubCommand::with_name("replay")
+            .about("Send what was sent to the Launchpad in a recorded session again, or \
+                    play a light show from a MIDI file")
+            .arg(Arg::with_name("file")
+                .required(true)
+                .help("Session file written by --record, or a .mid file")))
+        .subcommand(SubCommand::with_name("export")
+            .about("Write what was sent to the Launchpad in a recorded session as a MIDI file")
+            .arg(Arg::with_name("session")
+                .required(true)
+                .help("Session file written by --record"))
+            .arg(Arg::with_name("output")
+                .required(true)
+                .help("MIDI file to write")))
+}
+
+/// Arguments shared by the subcommands showing images
+fn image_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("file")
+             .required(true)
+             .help("Image to display"),
+         Arg::with_name("buttons")
+             .short("b")
+             .long("buttons")
+             .help("Also use the top and side buttons"),
+         Arg::with_name("stretch")
+             .short("s")
+             .long("stretch")
+             .help("Stretch the image to fit instead of cropping it square"),
+         Arg::with_name("palette")
+             .short("p")
+             .long("palette")
+             .takes_value(true)
+             .possible_values(&["nearest", "ordered", "floyd-steinberg"])
+             .help("Match palette colors with the given dithering instead of sending RGB")]
 }
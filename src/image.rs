@@ -0,0 +1,307 @@
+//! Loading PNG and PPM images and animated GIFs to show on the grid.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use gif;
+use launchpad::RGBColor;
+use png;
+
+/// A decoded image, row-major with the top row first
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<RGBColor>,
+}
+
+/// Load a PNG or PPM (P3 or P6) image, picking the format from the file's
+/// contents. Transparent pixels are blended onto black.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Image, String> {
+    let path = path.as_ref();
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|f| BufReader::new(f).read_to_end(&mut data))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let result = if data.starts_with(b"\x89PNG") {
+        load_png(&data)
+    } else if data.starts_with(b"P3") || data.starts_with(b"P6") {
+        load_ppm(&data)
+    } else {
+        Err("not a PNG or PPM image".to_string())
+    };
+    result.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn load_png(data: &[u8]) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+
+    let channels = info.color_type.samples();
+    let pixels = buf[..info.buffer_size()]
+        .chunks(channels)
+        .map(|p| match *p {
+            [v] => RGBColor::new(v, v, v),
+            [v, a] => blend(v, v, v, a),
+            [r, g, b] => RGBColor::new(r, g, b),
+            [r, g, b, a] => blend(r, g, b, a),
+            _ => RGBColor::new(0, 0, 0),
+        })
+        .collect();
+
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        pixels,
+    })
+}
+
+fn blend(r: u8, g: u8, b: u8, alpha: u8) -> RGBColor {
+    let scale = |v: u8| (v as u16 * alpha as u16 / 255) as u8;
+    RGBColor::new(scale(r), scale(g), scale(b))
+}
+
+fn load_ppm(data: &[u8]) -> Result<Image, String> {
+    // Header: magic, width, height, maxval, separated by whitespace and
+    // comments, then a single whitespace byte before binary data
+    let mut pos = 2;
+    let mut header = [0usize; 3];
+    for field in header.iter_mut() {
+        loop {
+            match data.get(pos) {
+                Some(b'#') => {
+                    while data.get(pos).is_some_and(|&c| c != b'\n') {
+                        pos += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => pos += 1,
+                _ => break,
+            }
+        }
+        let start = pos;
+        while data.get(pos).is_some_and(|c| c.is_ascii_digit()) {
+            pos += 1;
+        }
+        *field = std::str::from_utf8(&data[start..pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or("bad PPM header")?;
+    }
+    let [width, height, maxval] = header;
+    if maxval == 0 || maxval > 255 {
+        return Err("only 8 bit PPM images are supported".to_string());
+    }
+    if width == 0 || height == 0 {
+        return Err("PPM image is empty".to_string());
+    }
+    let size = width.checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or("PPM image is too large")?;
+
+    let samples: Vec<usize> = if data.starts_with(b"P6") {
+        data.get(pos + 1..)
+            .ok_or("PPM image is truncated")?
+            .iter()
+            .map(|&v| v as usize)
+            .collect()
+    } else {
+        std::str::from_utf8(&data[pos..])
+            .map_err(|e| e.to_string())?
+            .split_whitespace()
+            .map(|v| v.parse().map_err(|_| "bad PPM sample".to_string()))
+            .collect::<Result<_, _>>()?
+    };
+    if samples.len() < size {
+        return Err("PPM image is truncated".to_string());
+    }
+
+    let pixels = samples.chunks(3)
+        .take(width * height)
+        .map(|p| {
+            let scale = |v: usize| (v.min(maxval) * 255 / maxval) as u8;
+            RGBColor::new(scale(p[0]), scale(p[1]), scale(p[2]))
+        })
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// The frames of an animated GIF
+pub struct Animation<T> {
+    pub frames: Vec<(T, Duration)>,
+    /// How many times the file asks to be played, `None` for forever
+    pub plays: Option<usize>,
+}
+
+/// Shortest frame delay honored. Like browsers, shorter delays in the file
+/// are played at 100ms.
+const MIN_GIF_DELAY: u16 = 2;
+
+/// Load every frame of an animated GIF, composited the way a viewer would
+/// show them. Transparent areas are black. Each frame is passed to `convert`
+/// as soon as it is composited, so only what it returns is kept.
+pub fn load_gif<P, T, F>(path: P, convert: F) -> Result<Animation<T>, String>
+    where P: AsRef<Path>,
+          F: FnMut(&Image) -> T
+{
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    decode_gif(BufReader::new(file), convert).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn decode_gif<R: Read, T, F: FnMut(&Image) -> T>(data: R, mut convert: F) -> Result<Animation<T>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(data).map_err(|e| e.to_string())?;
+
+    let width = decoder.width() as usize;
+    let height = decoder.height() as usize;
+    if width == 0 || height == 0 {
+        return Err("GIF is empty".to_string());
+    }
+    let mut screen = Image {
+        width,
+        height,
+        pixels: vec![RGBColor::new(0, 0, 0); width * height],
+    };
+    let mut frames = Vec::new();
+
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        if frame.width == 0 || frame.height == 0 {
+            continue;
+        }
+        let previous = match frame.dispose {
+            gif::DisposalMethod::Previous => Some(screen.pixels.clone()),
+            _ => None,
+        };
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        let (frame_width, frame_height) = (frame.width as usize, frame.height as usize);
+
+        for (i, p) in frame.buffer.chunks(4).enumerate() {
+            let (x, y) = (left + i % frame_width, top + i / frame_width);
+            if x < width && y < height && p[3] != 0 {
+                screen.pixels[y * width + x] = RGBColor::new(p[0], p[1], p[2]);
+            }
+        }
+
+        let delay = if frame.delay < MIN_GIF_DELAY { 10 } else { frame.delay };
+        frames.push((convert(&screen), Duration::from_millis(delay as u64 * 10)));
+
+        match frame.dispose {
+            gif::DisposalMethod::Background => {
+                for y in top..(top + frame_height).min(height) {
+                    for x in left..(left + frame_width).min(width) {
+                        screen.pixels[y * width + x] = RGBColor::new(0, 0, 0);
+                    }
+                }
+            }
+            gif::DisposalMethod::Previous => screen.pixels = previous.unwrap(),
+            _ => {}
+        }
+    }
+
+    if frames.is_empty() {
+        return Err("GIF has no frames".to_string());
+    }
+
+    let plays = match decoder.repeat() {
+        gif::Repeat::Infinite => None,
+        gif::Repeat::Finite(repeats) => Some(repeats as usize + 1),
+    };
+    Ok(Animation { frames, plays })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppm() {
+        let image = load_ppm(b"P3\n# two pixels\n2 1\n15\n15 0 0  0 15 15\n").unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, vec![RGBColor::new(255, 0, 0), RGBColor::new(0, 255, 255)]);
+
+        let image = load_ppm(b"P6 1 1 255\n\x01\x02\x03").unwrap();
+        assert_eq!(image.pixels, vec![RGBColor::new(1, 2, 3)]);
+    }
+
+    #[test]
+    fn malformed_ppm() {
+        let bad: [&[u8]; 9] = [b"P6",
+                               b"P6 1 1",
+                               b"P6 1 1 255",
+                               b"P6 1 1 255\n\x01\x02",
+                               b"P6 0 0 255\n",
+                               b"P6 1 1 65535\n\x01\x02\x03",
+                               b"P6 99999999999 99999999999 255\n",
+                               b"P3 1 1 255 1 2 x",
+                               b"P3 one 1 255 1 2 3"];
+        for data in bad.iter() {
+            assert!(load_ppm(data).is_err(), "{:?}", String::from_utf8_lossy(data));
+        }
+    }
+
+    fn encode_png(color: png::ColorType, pixels: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, 2, 1);
+            encoder.set_color(color);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(pixels).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn png() {
+        let data = encode_png(png::ColorType::Rgba, &[255, 0, 0, 255, 255, 255, 255, 0]);
+        let image = load_png(&data).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, vec![RGBColor::new(255, 0, 0), RGBColor::new(0, 0, 0)]);
+
+        let image = load_png(&encode_png(png::ColorType::Grayscale, &[0, 128])).unwrap();
+        assert_eq!(image.pixels, vec![RGBColor::new(0, 0, 0), RGBColor::new(128, 128, 128)]);
+    }
+
+    #[test]
+    fn malformed_png() {
+        let data = encode_png(png::ColorType::Rgb, &[1, 2, 3, 4, 5, 6]);
+        assert!(load_png(&data[..data.len() / 2]).is_err());
+        assert!(load_png(b"\x89PNG\r\n\x1a\n").is_err());
+    }
+
+    #[test]
+    fn gif() {
+        let mut data = Vec::new();
+        {
+            let palette = [0, 0, 0, 255, 0, 0];
+            let mut encoder = gif::Encoder::new(&mut data, 2, 2, &palette).unwrap();
+            encoder.set_repeat(gif::Repeat::Finite(1)).unwrap();
+            let mut frame = gif::Frame::from_indexed_pixels(2, 2, vec![1, 0, 0, 0], None);
+            frame.delay = 50;
+            encoder.write_frame(&frame).unwrap();
+            let mut frame = gif::Frame::from_indexed_pixels(1, 1, vec![1], None);
+            frame.left = 1;
+            frame.top = 1;
+            encoder.write_frame(&frame).unwrap();
+        }
+        let animation = decode_gif(&data[..], |image| image.pixels.clone()).unwrap();
+        let red = RGBColor::new(255, 0, 0);
+        let black = RGBColor::new(0, 0, 0);
+        assert_eq!(animation.plays, Some(2));
+        assert_eq!(animation.frames,
+                   vec![(vec![red, black, black, black], Duration::from_millis(500)),
+                        (vec![red, black, black, red], Duration::from_millis(100))]);
+
+        assert!(decode_gif(&data[..data.len() / 2], |_| ()).is_err());
+    }
+}
@@ -1,14 +1,32 @@
 extern crate launchpad;
 extern crate clap;
+extern crate crossterm;
+extern crate gif;
+extern crate png;
 extern crate portmidi as pm;
+#[macro_use]
+extern crate serde;
+extern crate serde_json;
+extern crate toml;
+extern crate tungstenite;
 
 use launchpad::*;
+use clap::ArgMatches;
 
+use std::fs::File;
+use std::io;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::process;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 mod cli;
+mod image;
+mod osc;
+mod scene;
+mod serve;
+mod tui;
 
 fn main() {
     // initialize the PortMidi context.
@@ -16,30 +34,409 @@ fn main() {
     let args = cli::build_args();
     let inpt = args.get_matches();
     if inpt.occurrences_of("list") > 0 {
-        list();
+        list(&inpt);
     }
 
-    run();
+    match inpt.subcommand() {
+        ("image", Some(sub)) => show_image(sub),
+        ("gif", Some(sub)) => play_gif(sub),
+        ("seq", Some(sub)) => sequence(sub),
+        ("map", Some(sub)) => remap(sub),
+        ("osc", Some(sub)) => bridge_osc(sub),
+        ("serve", Some(sub)) => serve(sub),
+        ("replay", Some(sub)) => replay(sub),
+        ("export", Some(sub)) => export(sub),
+        ("scene", Some(sub)) => show_scene(sub),
+        _ => {}
+    }
+
+    run(&inpt);
+}
+
+/// What `--list --json` prints
+#[derive(Serialize)]
+struct Listing {
+    devices: Vec<MidiDevice>,
+    /// The ports a Launchpad Mk2 is driven through, if one is connected
+    input: Option<i32>,
+    output: Option<i32>,
+}
+
+fn list(args: &ArgMatches) -> ! {
+    let context = fail(pm::PortMidi::new());
+    let devs = fail(context.devices());
+    let (input, output) = guess_ports(&devs);
+    let devices = midi_devices(&devs);
+
+    if args.is_present("json") {
+        let listing = Listing { devices, input, output };
+        println!("{}", fail(serde_json::to_string_pretty(&listing)));
+        process::exit(0);
+    }
+
+    let ids = |ids: &[i32]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ");
+    for device in &devices {
+        match device.model {
+            Some(model) if model.supported() => println!("{} [{}]", device.name, model.name()),
+            Some(model) => println!("{} [{}, not supported]", device.name, model.name()),
+            None => println!("{}", device.name),
+        }
+        if !device.inputs.is_empty() {
+            println!("    input {}", ids(&device.inputs));
+        }
+        if !device.outputs.is_empty() {
+            println!("    output {}", ids(&device.outputs));
+        }
+    }
+    match (input, output) {
+        (Some(input), Some(output)) => {
+            println!("\nThe Launchpad Mk2 is driven through input {} and output {}", input, output)
+        }
+        _ => println!("\nNo Launchpad Mk2 found"),
+    }
+
+    process::exit(0);
+}
+
+fn fail<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        tui::restore();
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+/// The device to drive: an emulated one with `--emulate`, or else the first
+/// Launchpad Mark 2 found, through `midi` if given. With `--mirror`, what it
+/// is sent is shown in the terminal, and with `--record` everything it sends
+/// and receives is written to a session file.
+fn device(args: &ArgMatches, midi: Option<&pm::PortMidi>) -> LaunchpadMk2 {
+    let mut lpad = if args.is_present("emulate") {
+        fail(tui::emulate())
+    } else {
+        let mut lpad = match midi {
+            Some(midi) => LaunchpadMk2::guess_from(midi),
+            None => LaunchpadMk2::guess(),
+        };
+        if args.is_present("mirror") {
+            fail(tui::mirror(&mut lpad));
+        }
+        lpad
+    };
+    if let Some(path) = args.value_of("record") {
+        let file = fail(File::create(path));
+        fail(Recorder::attach_writing(&mut lpad, file));
+    }
+    lpad
+}
+
+/// Whether the device is shown in the terminal, which owns the screen then
+fn terminal_view(args: &ArgMatches) -> bool {
+    args.is_present("emulate") || args.is_present("mirror")
+}
+
+/// Exit once done with the device, or keep showing it in the terminal until
+/// the user quits
+fn done(args: &ArgMatches) -> ! {
+    if terminal_view(args) {
+        tui::wait();
+    }
+    process::exit(0);
+}
+
+/// Whether the user asked to quit with Enter, see `watch_quit`
+static QUIT: AtomicBool = AtomicBool::new(false);
+
+/// Let the user quit a loop which needs to tidy up first, such as sending
+/// note offs: with `q` in a terminal view, or else with Enter. See `quitting`.
+fn watch_quit(args: &ArgMatches) {
+    if terminal_view(args) {
+        tui::defer_quit();
+        return;
+    }
+    eprintln!("Press Enter to quit.");
+    thread::spawn(|| {
+        let mut line = String::new();
+        // Without a terminal to read from, only an interrupt stops the program
+        if io::stdin().read_line(&mut line).is_ok_and(|len| len > 0) {
+            QUIT.store(true, Ordering::SeqCst);
+        }
+    });
+}
+
+/// Whether the user asked to quit, after `watch_quit`
+fn quitting() -> bool {
+    QUIT.load(Ordering::SeqCst) || tui::quitting()
+}
+
+/// Exit once a loop has tidied up after the user quit
+fn quit() -> ! {
+    tui::restore();
+    process::exit(0);
+}
+
+/// Convert an image to a frame as asked by the `image_args` arguments
+fn image_frame(args: &ArgMatches, img: &image::Image) -> Frame {
+    let buttons = args.is_present("buttons");
+    let (pixels, width, height) = if args.is_present("stretch") {
+        (img.pixels.clone(), img.width, img.height)
+    } else {
+        crop_to_aspect(&img.pixels, img.width, img.height, 1, 1)
+    };
+    let dither = match args.value_of("palette") {
+        Some("nearest") => Some(Dither::None),
+        Some("ordered") => Some(Dither::Ordered),
+        Some("floyd-steinberg") => Some(Dither::FloydSteinberg),
+        _ => None,
+    };
+
+    Frame::from_image(&pixels, width, height, buttons, dither)
+}
+
+fn show_image(args: &ArgMatches) -> ! {
+    let img = fail(image::load(args.value_of("file").unwrap()));
+
+    let mut lpad = device(args, None);
+    lpad.draw_frame(&image_frame(args, &img));
+
+    done(args);
+}
+
+fn show_scene(args: &ArgMatches) -> ! {
+    let scene = fail(scene::Scene::load(args.value_of("file").unwrap()));
+
+    let mut lpad = device(args, None);
+    scene.apply(&mut lpad);
+
+    done(args);
+}
+
+fn play_gif(args: &ArgMatches) -> ! {
+    let animation = fail(image::load_gif(args.value_of("file").unwrap(), |img| image_frame(args, img)));
+    let plays = match args.value_of("loops") {
+        Some(loops) => {
+            match fail(loops.parse::<usize>()) {
+                0 => None,
+                n => Some(n),
+            }
+        }
+        None => animation.plays,
+    };
+    let frames = animation.frames;
+
+    let mut lpad = device(args, None);
+    let mut shown = Frame::new();
+    lpad.draw_frame(&shown);
+
+    let mut played = 0;
+    let mut deadline = Instant::now();
+    while plays.map_or(true, |plays| played < plays) {
+        for &(ref frame, delay) in &frames {
+            lpad.draw_frame_diff(frame, &shown);
+            shown = frame.clone();
+
+            deadline += delay;
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+        }
+        played += 1;
+    }
+
+    done(args);
 }
 
-fn list() -> ! {
-    let context = pm::PortMidi::new().unwrap();
-    let devs = context.devices().unwrap();
+fn sequence(args: &ArgMatches) -> ! {
+    let midi = fail(pm::PortMidi::new());
+    let mut lpad = device(args, Some(&midi));
+
+    let output_id = fail(args.value_of("output").unwrap().parse());
+    let mut output = fail(midi.device(output_id).and_then(|d| midi.output_port(d, 1024)));
+    let clock = args.value_of("clock").map(|id| {
+        let id = fail(id.parse());
+        fail(midi.device(id).and_then(|d| midi.input_port(d, 1024)))
+    });
+    let bpm = fail(tempo(args.value_of("bpm").unwrap()));
+    let steps = fail(args.value_of("steps").unwrap().parse());
+
+    watch_quit(args);
+    let mut seq = Sequencer::new(steps);
+    let interval = Duration::from_secs_f64(60.0 / bpm / STEPS_PER_BEAT as f64);
+    let mut next_step = Instant::now();
+    let mut shown = Frame::new();
+    lpad.draw_frame(&shown);
 
-    for d in devs {
-        println!("{:?}", d);
+    loop {
+        for event in lpad.poll_events() {
+            seq.handle(&event);
+        }
+
+        let msgs = match clock {
+            Some(ref clock) => {
+                fail(clock.read_n(1024))
+                    .unwrap_or_default()
+                    .iter()
+                    .flat_map(|e| seq.clock(e.message.status))
+                    .collect()
+            }
+            None if Instant::now() >= next_step => {
+                next_step += interval;
+                seq.advance()
+            }
+            None => Vec::new(),
+        };
+        for msg in msgs {
+            fail(output.write_message(msg));
+        }
+        if quitting() {
+            for msg in seq.stop() {
+                fail(output.write_message(msg));
+            }
+            quit();
+        }
+
+        let mut frame = Frame::new();
+        seq.draw(&mut frame);
+        lpad.draw_frame_diff(&frame, &shown);
+        shown = frame;
+
+        // there is no blocking receive method in PortMidi
+        thread::sleep(Duration::from_millis(1));
     }
+}
+
+/// Read a tempo in BPM, in the range the clock accepts
+fn tempo(text: &str) -> Result<f64, String> {
+    match text.parse() {
+        Ok(bpm) if (MIN_BPM..=MAX_BPM).contains(&bpm) => Ok(bpm),
+        _ => Err(format!("the tempo must be from {} to {} BPM", MIN_BPM, MAX_BPM)),
+    }
+}
+
+fn remap(args: &ArgMatches) -> ! {
+    let mut mapping = fail(Mapping::load(args.value_of("config").unwrap()));
+
+    let midi = fail(pm::PortMidi::new());
+    let mut lpad = device(args, Some(&midi));
+    let output_id = fail(args.value_of("output").unwrap().parse());
+    let mut output = fail(midi.device(output_id).and_then(|d| midi.output_port(d, 1024)));
+
+    watch_quit(args);
+    let mut shown = Frame::new();
+    lpad.draw_frame(&shown);
+
+    loop {
+        for event in lpad.poll_events() {
+            for msg in mapping.handle(&event) {
+                fail(output.write_message(msg));
+            }
+        }
+        if quitting() {
+            for msg in mapping.release_all() {
+                fail(output.write_message(msg));
+            }
+            quit();
+        }
+
+        let mut frame = Frame::new();
+        mapping.draw(&mut frame);
+        lpad.draw_frame_diff(&frame, &shown);
+        shown = frame;
+
+        // there is no blocking receive method in PortMidi
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn bridge_osc(args: &ArgMatches) -> ! {
+    let socket = fail(UdpSocket::bind(args.value_of("listen").unwrap()));
+    fail(socket.set_nonblocking(true));
+    let target = args.value_of("target").unwrap();
+
+    let mut lpad = device(args, None);
+    let mut buf = [0u8; 65536];
 
+    loop {
+        // Lighting messages are not worth exiting over, so just report them
+        while let Ok(len) = socket.recv(&mut buf) {
+            match osc::decode(&buf[..len]) {
+                Ok(msgs) => {
+                    for msg in msgs {
+                        if let Err(e) = osc::apply(&mut lpad, &msg) {
+                            eprintln!("{}", e);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        for event in lpad.poll_events() {
+            if let Some(msg) = osc::event_message(&event) {
+                if let Err(e) = socket.send_to(&msg.encode(), target) {
+                    eprintln!("{}: {}", target, e);
+                }
+            }
+        }
+
+        // there is no blocking receive method in PortMidi
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn serve(args: &ArgMatches) -> ! {
+    let requests = fail(serve::listen(args.value_of("listen").unwrap()));
+    let mut lpad = device(args, None);
+    let mut server = serve::Server::new(&mut lpad);
+
+    loop {
+        while let Ok(request) = requests.try_recv() {
+            server.handle(&mut lpad, request);
+        }
+        for event in lpad.poll_events() {
+            server.broadcast(&event);
+        }
+
+        // there is no blocking receive method in PortMidi
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn replay(args: &ArgMatches) -> ! {
+    let path = args.value_of("file").unwrap();
+    let session = if path.ends_with(".mid") || path.ends_with(".midi") {
+        fail(Session::load_midi_file(path))
+    } else {
+        fail(Session::load(path))
+    };
+
+    let lpad = device(args, None);
+    fail(session.play(&lpad));
+
+    done(args);
+}
+
+fn export(args: &ArgMatches) -> ! {
+    let session = fail(Session::load(args.value_of("session").unwrap()));
+    fail(session.save_midi_file(args.value_of("output").unwrap()));
     process::exit(0);
 }
 
-fn run() {
-    println!("Please enjoy!");
+/// Tell what the demo does next, unless the terminal view would be garbled
+fn say(args: &ArgMatches, text: &str) {
+    if !terminal_view(args) {
+        println!("{}", text);
+    }
+}
+
+fn run(args: &ArgMatches) {
+    say(args, "Please enjoy!");
     let timeout = Duration::from_millis(1);
-    let mut lpad = LaunchpadMk2::guess();
+    let mut lpad = device(args, None);
 
-    println!("Clear screen...");
-    lpad.light_all(0);
+    say(args, "Clear screen...");
+    lpad.light_all(palette::OFF);
 
     // println!("Fuzzy!");
     // for r in 0..255 {
@@ -61,40 +458,44 @@ fn run() {
     // thread::sleep(Duration::from_millis(500));
     // println!("Fuzzy!");
 
-    println!("Columns on!");
+    say(args, "Columns on!");
     for i in 0..9 {
         lpad.light_column(&ColorColumn {
             column: i,
-            color: 5,
+            color: palette::RED,
         });
         thread::sleep(Duration::from_millis(25));
     }
 
     thread::sleep(Duration::from_millis(500));
 
-    println!("Columns off!");
+    say(args, "Columns off!");
     for i in 0..9 {
-        lpad.light_row(&ColorRow { row: i, color: 0 });
+        lpad.light_row(&ColorRow { row: i, color: palette::OFF });
         thread::sleep(Duration::from_millis(25));
     }
 
     thread::sleep(Duration::from_millis(500));
 
-    println!("Whole panel colors...");
-    for color in vec![18, 54, 13, 104, 0] {
+    say(args, "Whole panel colors...");
+    for color in vec![palette::LIME_DIM,
+                      palette::MAGENTA_DIM,
+                      palette::YELLOW,
+                      palette::NAVY,
+                      palette::OFF] {
         lpad.light_all(color);
         thread::sleep(Duration::from_millis(1000));
     }
 
     // Playground
 
-    println!("Light rows in a silly way");
+    say(args, "Light rows in a silly way");
     for row in 1..9 {
         for column in 1..9 {
             let x = 10 * row + column;
             lpad.light_led(&ColorLed {
                 position: x,
-                color: 88,
+                color: palette::NEON_GREEN,
             });
             thread::sleep(Duration::from_millis(1));
         }
@@ -103,63 +504,68 @@ fn run() {
 
     thread::sleep(Duration::from_millis(500));
 
-    println!("Bottom Right to Top Left");
-    lpad.light_leds(&vec![&ColorLed {position: 11, color: 41,},
-                          &ColorLed {position: 22, color: 41,},
-                          &ColorLed {position: 33, color: 41,},
-                          &ColorLed {position: 44, color: 41,},
-                          &ColorLed {position: 55, color: 41,},
-                          &ColorLed {position: 66, color: 41,},
-                          &ColorLed {position: 77, color: 41,},
-                          &ColorLed {position: 88, color: 41,},]);
+    say(args, "Bottom Right to Top Left");
+    lpad.light_leds(&vec![&ColorLed {position: 11, color: palette::AZURE,},
+                          &ColorLed {position: 22, color: palette::AZURE,},
+                          &ColorLed {position: 33, color: palette::AZURE,},
+                          &ColorLed {position: 44, color: palette::AZURE,},
+                          &ColorLed {position: 55, color: palette::AZURE,},
+                          &ColorLed {position: 66, color: palette::AZURE,},
+                          &ColorLed {position: 77, color: palette::AZURE,},
+                          &ColorLed {position: 88, color: palette::AZURE,},]);
 
     thread::sleep(Duration::from_millis(500));
 
-    println!("Bottom Left to Top Right");
-    lpad.light_leds(&vec![&ColorLed {position: 81, color: 5,},
-                          &ColorLed {position: 72, color: 5,},
-                          &ColorLed {position: 63, color: 5,},
-                          &ColorLed {position: 54, color: 5,},
-                          &ColorLed {position: 45, color: 5,},
-                          &ColorLed {position: 36, color: 5,},
-                          &ColorLed {position: 27, color: 5,},
-                          &ColorLed {position: 18, color: 5,},]);
+    say(args, "Bottom Left to Top Right");
+    lpad.light_leds(&vec![&ColorLed {position: 81, color: palette::RED,},
+                          &ColorLed {position: 72, color: palette::RED,},
+                          &ColorLed {position: 63, color: palette::RED,},
+                          &ColorLed {position: 54, color: palette::RED,},
+                          &ColorLed {position: 45, color: palette::RED,},
+                          &ColorLed {position: 36, color: palette::RED,},
+                          &ColorLed {position: 27, color: palette::RED,},
+                          &ColorLed {position: 18, color: palette::RED,},]);
 
     thread::sleep(Duration::from_millis(500));
 
-    println!("Right controls on");
-    lpad.light_leds(&vec![&ColorLed {position: 19, color: 3,},
-                          &ColorLed {position: 29, color: 3,},
-                          &ColorLed {position: 39, color: 3,},
-                          &ColorLed {position: 49, color: 3,},
-                          &ColorLed {position: 59, color: 3,},
-                          &ColorLed {position: 69, color: 3,},
-                          &ColorLed {position: 79, color: 3,},
-                          &ColorLed {position: 89, color: 3,},]);
+    say(args, "Right controls on");
+    lpad.light_leds(&vec![&ColorLed {position: 19, color: palette::WHITE,},
+                          &ColorLed {position: 29, color: palette::WHITE,},
+                          &ColorLed {position: 39, color: palette::WHITE,},
+                          &ColorLed {position: 49, color: palette::WHITE,},
+                          &ColorLed {position: 59, color: palette::WHITE,},
+                          &ColorLed {position: 69, color: palette::WHITE,},
+                          &ColorLed {position: 79, color: palette::WHITE,},
+                          &ColorLed {position: 89, color: palette::WHITE,},]);
 
     thread::sleep(Duration::from_millis(500));
 
-    println!("Top controls on");
-    lpad.light_leds(&vec![&ColorLed {position: 104, color: 4,},
-                          &ColorLed {position: 105, color: 4,},
-                          &ColorLed {position: 106, color: 4,},
-                          &ColorLed {position: 107, color: 4,},
-                          &ColorLed {position: 108, color: 4,},
-                          &ColorLed {position: 109, color: 4,},
-                          &ColorLed {position: 110, color: 4,},
-                          &ColorLed {position: 111, color: 4,},]);
+    say(args, "Top controls on");
+    lpad.light_leds(&vec![&ColorLed {position: 104, color: palette::RED_LIGHT,},
+                          &ColorLed {position: 105, color: palette::RED_LIGHT,},
+                          &ColorLed {position: 106, color: palette::RED_LIGHT,},
+                          &ColorLed {position: 107, color: palette::RED_LIGHT,},
+                          &ColorLed {position: 108, color: palette::RED_LIGHT,},
+                          &ColorLed {position: 109, color: palette::RED_LIGHT,},
+                          &ColorLed {position: 110, color: palette::RED_LIGHT,},
+                          &ColorLed {position: 111, color: palette::RED_LIGHT,},]);
 
 
     thread::sleep(Duration::from_millis(500));
-    println!("Blank screen");
-    lpad.light_all(0);
+    say(args, "Blank screen");
+    lpad.light_all(palette::OFF);
 
-    println!("Scroll Text");
-    lpad.scroll_text(27, false, &format!("{}Your {}Turn!", SCROLL_SLOWER, SCROLL_FASTER));
+    say(args, "Scroll Text");
+    let text = ScrollText::new()
+        .speed(ScrollSpeed::Slower)
+        .text("Your ")
+        .speed(ScrollSpeed::Faster)
+        .text("Turn!");
+    lpad.scroll_text(palette::EMERALD_DARK, false, text.as_str());
 
-    let mut foo = 0;
+    let mut foo: Color = 0;
 
-    println!("Blinky/Pulsy playground!");
+    say(args, "Blinky/Pulsy playground!");
     loop {
         if let Some(events) = lpad.poll() {
             // println!("{:?}", event);
@@ -0,0 +1,304 @@
+//! A bridge between the device and Open Sound Control over UDP.
+//!
+//! Coordinates count from the top left like `Frame`, and as in
+//! `LaunchpadMk2::set_row` and `set_column`: `x` is 0...8 from the left, 8
+//! being the side buttons, and `y` is 0...8 from the top, 8 being the top
+//! buttons.
+//!
+//! Received messages light the device:
+//!
+//! * `/led/x/y color`
+//! * `/row y color`, `/column x color` and `/all color`
+//! * `/scroll text [color]`, or `/scroll/loop text [color]` to repeat it
+//!   until an empty text is sent
+//!
+//! where a color is three numbers `r g b`, integers 0...255 or floats
+//! 0.0...1.0, or one palette index, palette name or `#rrggbb`. Presses and
+//! releases are sent as `/press x y` and `/release x y`, and
+//! `/scroll/finished` when a message which doesn't repeat has scrolled past.
+
+use launchpad::*;
+
+/// An OSC argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Float(f32),
+    Str(String),
+}
+
+/// An OSC message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub address: String,
+    pub args: Vec<Arg>,
+}
+
+impl Message {
+    pub fn new(address: &str, args: Vec<Arg>) -> Message {
+        Message {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    /// Encode the message as a packet
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = Vec::new();
+        push_str(&mut packet, &self.address);
+        let tags: String = Some(',')
+            .into_iter()
+            .chain(self.args.iter().map(|arg| match *arg {
+                Arg::Int(_) => 'i',
+                Arg::Float(_) => 'f',
+                Arg::Str(_) => 's',
+            }))
+            .collect();
+        push_str(&mut packet, &tags);
+        for arg in &self.args {
+            match *arg {
+                Arg::Int(i) => packet.extend_from_slice(&i.to_be_bytes()),
+                Arg::Float(f) => packet.extend_from_slice(&f.to_bits().to_be_bytes()),
+                Arg::Str(ref s) => push_str(&mut packet, s),
+            }
+        }
+        packet
+    }
+}
+
+/// Append a string, null terminated and padded to four bytes
+fn push_str(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    packet.push(0);
+    while packet.len() % 4 != 0 {
+        packet.push(0);
+    }
+}
+
+/// Decode a packet, flattening bundles into their messages. Argument types
+/// other than `i`, `f` and `s` are not supported.
+pub fn decode(packet: &[u8]) -> Result<Vec<Message>, String> {
+    let mut messages = Vec::new();
+    decode_into(packet, &mut messages)?;
+    Ok(messages)
+}
+
+fn decode_into(packet: &[u8], messages: &mut Vec<Message>) -> Result<(), String> {
+    let mut reader = Reader { data: packet, pos: 0 };
+
+    let address = reader.string()?;
+    if address == "#bundle" {
+        // Time tag, ignored as everything is applied on arrival
+        reader.take(8)?;
+        while reader.pos < packet.len() {
+            let size = reader.int()?;
+            if size < 0 {
+                return Err("bad bundle element size".to_string());
+            }
+            decode_into(reader.take(size as usize)?, messages)?;
+        }
+        return Ok(());
+    }
+    if !address.starts_with('/') {
+        return Err(format!("bad address: {}", address));
+    }
+
+    let tags = if reader.pos < packet.len() { reader.string()? } else { ",".to_string() };
+    let tags = tags.strip_prefix(',').ok_or("bad type tags")?;
+    let mut args = Vec::new();
+    for tag in tags.chars() {
+        args.push(match tag {
+            'i' => Arg::Int(reader.int()?),
+            'f' => Arg::Float(f32::from_bits(reader.int()? as u32)),
+            's' => Arg::Str(reader.string()?),
+            _ => return Err(format!("unsupported argument type: {}", tag)),
+        });
+    }
+
+    messages.push(Message { address, args });
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or("packet too short")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn int(&mut self) -> Result<i32, String> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let len = rest.iter().position(|&b| b == 0).ok_or("unterminated string")?;
+        let s = String::from_utf8(rest[..len].to_vec()).map_err(|_| "string not UTF-8")?;
+        self.take((len + 4) / 4 * 4)?;
+        Ok(s)
+    }
+}
+
+/// Light the device as a received message asks
+pub fn apply(lpad: &mut LaunchpadMk2, msg: &Message) -> Result<(), String> {
+    let parts: Vec<&str> = msg.address[1..].split('/').collect();
+    let bad = || format!("bad message: {} {:?}", msg.address, msg.args);
+
+    match (&parts[..], &msg.args[..]) {
+        (&["led", x, y], args) => {
+            let x = x.parse().map_err(|_| bad())?;
+            let y = y.parse().map_err(|_| bad())?;
+            let position = position(x, y).ok_or_else(bad)?;
+            lpad.set_led(position, color(args).ok_or_else(bad)?);
+        }
+        (&["row"], &[Arg::Int(y), ref args @ ..]) if (0..=8).contains(&y) => {
+            lpad.set_row(y as u8, color(args).ok_or_else(bad)?);
+        }
+        (&["column"], &[Arg::Int(x), ref args @ ..]) if (0..=8).contains(&x) => {
+            lpad.set_column(x as u8, color(args).ok_or_else(bad)?);
+        }
+        (&["all"], args) => lpad.set_all(color(args).ok_or_else(bad)?),
+        (&["scroll"], &[Arg::Str(ref text), ref args @ ..]) |
+        (&["scroll", "loop"], &[Arg::Str(ref text), ref args @ ..]) => {
+            let color = match *args {
+                [] => palette::WHITE,
+                ref args => color(args).ok_or_else(bad)?.nearest_palette(),
+            };
+            let text = ScrollText::new().text(text);
+            lpad.scroll_text(color, parts.len() == 2, text.as_str());
+        }
+        _ => return Err(bad()),
+    }
+    Ok(())
+}
+
+/// The position of the LED at `x`, `y`, if there is one
+fn position(x: u8, y: u8) -> Option<u8> {
+    let (x, y) = (x as usize, y as usize);
+    match (x, y) {
+        (8, 8) => None,
+        (0..=7, 8) => Some(top_position(x)),
+        (8, 0..=7) => Some(side_position(y)),
+        (0..=7, 0..=7) => Some(pad_position(x, y)),
+        _ => None,
+    }
+}
+
+/// The `x`, `y` of the LED at a position
+pub fn coordinates(position: u8) -> Option<(i32, i32)> {
+    match Location::of(position)? {
+        Location::Pad { x, y } => Some((x as i32, y as i32)),
+        Location::Top(x) => Some((x as i32, 8)),
+        Location::Side(y) => Some((8, y as i32)),
+    }
+}
+
+/// A color from `r g b`, a palette index, a palette name or `#rrggbb`
+fn color(args: &[Arg]) -> Option<LedColor> {
+    let channel = |arg: &Arg| match *arg {
+        Arg::Int(v) if (0..=255).contains(&v) => Some(v as u8),
+        Arg::Float(v) if (0.0..=1.0).contains(&v) => Some((v * 255.0).round() as u8),
+        _ => None,
+    };
+    match *args {
+        [ref r, ref g, ref b] => Some(LedColor::Rgb(RGBColor::new(channel(r)?, channel(g)?, channel(b)?))),
+        [Arg::Int(index)] if (0..128).contains(&index) => Some(LedColor::Palette(index as u8)),
+        [Arg::Str(ref name)] => parse_color(name),
+        _ => None,
+    }
+}
+
+/// The message to send for an event, if any
+pub fn event_message(event: &Event) -> Option<Message> {
+    let (address, position) = match *event {
+        Event::Press { position, .. } => ("/press", position),
+        Event::Release { position } => ("/release", position),
+        Event::ScrollFinished => return Some(Message::new("/scroll/finished", Vec::new())),
+    };
+    let (x, y) = coordinates(position)?;
+    Some(Message::new(address, vec![Arg::Int(x), Arg::Int(y)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let msg = Message::new("/scroll/loop",
+                               vec![Arg::Str("Hi".to_string()), Arg::Int(-3), Arg::Float(0.5)]);
+        let packet = msg.encode();
+        assert_eq!(packet.len() % 4, 0);
+        assert_eq!(decode(&packet), Ok(vec![msg.clone()]));
+
+        // A bundle of two messages, the second without type tags
+        let mut bundle = Vec::new();
+        push_str(&mut bundle, "#bundle");
+        bundle.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        bundle.extend_from_slice(&(packet.len() as i32).to_be_bytes());
+        bundle.extend_from_slice(&packet);
+        bundle.extend_from_slice(&4i32.to_be_bytes());
+        push_str(&mut bundle, "/a");
+        assert_eq!(decode(&bundle), Ok(vec![msg, Message::new("/a", Vec::new())]));
+    }
+
+    #[test]
+    fn malformed() {
+        let packet = Message::new("/row", vec![Arg::Int(1), Arg::Str("RED".to_string())]).encode();
+        // Cut anywhere but after the address, which leaves a message without
+        // type tags
+        for len in (1..packet.len()).filter(|&len| len != 8) {
+            assert!(decode(&packet[..len]).is_err(), "{} bytes", len);
+        }
+
+        let with_tags = |tags: &str| {
+            let mut packet = Vec::new();
+            push_str(&mut packet, "/all");
+            push_str(&mut packet, tags);
+            packet.extend_from_slice(&[0; 8]);
+            decode(&packet)
+        };
+        assert!(with_tags(",i").is_ok());
+        assert!(with_tags("i").is_err());
+        assert!(with_tags(",b").is_err());
+        assert!(decode(b"all\0").is_err());
+        assert!(decode(b"/all").is_err());
+        assert!(decode(b"/\xFF\0\0").is_err());
+
+        let mut bundle = Vec::new();
+        push_str(&mut bundle, "#bundle");
+        bundle.extend_from_slice(&[0; 8]);
+        bundle.extend_from_slice(&(-4i32).to_be_bytes());
+        assert!(decode(&bundle).is_err());
+    }
+
+    #[test]
+    fn frame_coordinates() {
+        assert_eq!(position(0, 0), Some(pad_position(0, 0)));
+        assert_eq!(position(8, 0), Some(side_position(0)));
+        assert_eq!(position(0, 8), Some(top_position(0)));
+        assert_eq!(position(8, 8), None);
+        assert_eq!(position(9, 0), None);
+        for position in (11..=89).chain(104..=111).filter(|&p| Location::of(p).is_some()) {
+            let (x, y) = coordinates(position).unwrap();
+            assert_eq!(super::position(x as u8, y as u8), Some(position));
+        }
+
+        let (emulator, mut lpad) = Emulator::new();
+        apply(&mut lpad, &Message::new("/led/1/0", vec![Arg::Str("RED".to_string())])).unwrap();
+        apply(&mut lpad, &Message::new("/row", vec![Arg::Int(7), Arg::Int(palette::BLUE as i32)])).unwrap();
+        assert!(apply(&mut lpad, &Message::new("/row", vec![Arg::Int(9), Arg::Int(5)])).is_err());
+        let screen = emulator.screen();
+        assert_eq!(screen.led(pad_position(1, 0)).color, LedColor::Palette(palette::RED));
+        assert_eq!(screen.led(pad_position(4, 7)).color, LedColor::Palette(palette::BLUE));
+
+        assert_eq!(event_message(&Event::Press { position: pad_position(2, 6), velocity: 1 }),
+                   Some(Message::new("/press", vec![Arg::Int(2), Arg::Int(6)])));
+    }
+}
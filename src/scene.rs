@@ -0,0 +1,250 @@
+//! Static LED layouts described in TOML or JSON files.
+//!
+//! ```toml
+//! all = "off"
+//! rows = [{ row = 0, color = "RED_DIM" }]
+//! columns = [{ column = 7, color = "#00ff00" }]
+//! leds = [
+//!     { x = 3, y = 4, color = "WHITE" },
+//!     { top = 0, color = 5, effect = "pulse" },
+//!     { side = 2, color = "BLUE", effect = "flash" },
+//! ]
+//!
+//! [scroll]
+//! text = "Hello"
+//! color = "WHITE"
+//! loop = true
+//! ```
+//!
+//! Every LED starts as `all`, then rows, columns and single LEDs are applied
+//! in that order, so the most specific setting wins. Rows count from the top
+//! and columns from the left, 0...7, like pads' `x` and `y`; `top` and `side`
+//! buttons count from the left and the top. A flashing LED alternates between
+//! the color it would otherwise have and its own, and a pulsing one fades in
+//! and out; both use the nearest palette color. Colors are palette indexes,
+//! palette names, `"#rrggbb"` or `"off"`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::de::IgnoredAny;
+use launchpad::{Frame, LaunchpadMk2, LedColor, Location, ScrollText, GRID_SIZE, palette};
+use serde_json;
+use toml;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    #[serde(default)]
+    all: LedColor,
+    #[serde(default)]
+    rows: Vec<Row>,
+    #[serde(default)]
+    columns: Vec<Column>,
+    #[serde(default)]
+    leds: Vec<Led>,
+    scroll: Option<Scroll>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Row {
+    row: usize,
+    color: LedColor,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Column {
+    column: usize,
+    color: LedColor,
+}
+
+#[derive(Debug, Deserialize)]
+struct Led {
+    #[serde(flatten)]
+    location: Location,
+    color: LedColor,
+    effect: Option<Effect>,
+    /// The remaining fields, as `deny_unknown_fields` does not work together
+    /// with `flatten`; see `unknown_field`
+    #[serde(flatten)]
+    extra: BTreeMap<String, IgnoredAny>,
+}
+
+impl Led {
+    /// The first field which is neither the location's nor the LED's own
+    fn unknown_field(&self) -> Option<&str> {
+        let location: &[&str] = match self.location {
+            Location::Pad { .. } => &["x", "y"],
+            Location::Top(_) => &["top"],
+            Location::Side(_) => &["side"],
+        };
+        self.extra.keys().map(String::as_str).find(|field| !location.contains(field))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Effect {
+    Flash,
+    Pulse,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scroll {
+    text: String,
+    color: Option<LedColor>,
+    #[serde(default, rename = "loop")]
+    looping: bool,
+}
+
+impl Scene {
+    /// Load a scene, as JSON if the file name ends in `.json` and as TOML
+    /// otherwise, and check it can be shown
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let json = path.extension().is_some_and(|ext| ext == "json");
+        Scene::parse(&text, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str, json: bool) -> Result<Scene, String> {
+        let scene: Scene = if json {
+            serde_json::from_str(text).map_err(|e| e.to_string())?
+        } else {
+            toml::from_str(text).map_err(|e| e.to_string())?
+        };
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(row) = self.rows.iter().find(|r| r.row >= GRID_SIZE) {
+            return Err(format!("no row {}, rows are 0 to {}", row.row, GRID_SIZE - 1));
+        }
+        if let Some(column) = self.columns.iter().find(|c| c.column >= GRID_SIZE) {
+            return Err(format!("no column {}, columns are 0 to {}", column.column, GRID_SIZE - 1));
+        }
+        if let Some(field) = self.leds.iter().filter_map(Led::unknown_field).next() {
+            return Err(format!("unknown field `{}` in an LED", field));
+        }
+        if let Some(ref scroll) = self.scroll {
+            ScrollText::new().ascii(&scroll.text).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// The LEDs' colors, before any flashing or pulsing
+    fn frame(&self) -> Frame {
+        let mut frame = Frame::new();
+        for index in 0..GRID_SIZE {
+            frame.set_top(index, self.all);
+            frame.set_side(index, self.all);
+        }
+        frame.fill_pads(self.all);
+        for row in &self.rows {
+            for x in 0..GRID_SIZE {
+                frame.set_pad(x, row.row, row.color);
+            }
+        }
+        for column in &self.columns {
+            for y in 0..GRID_SIZE {
+                frame.set_pad(column.column, y, column.color);
+            }
+        }
+        for led in self.leds.iter().filter(|led| led.effect.is_none()) {
+            frame.set(led.location, led.color);
+        }
+        frame
+    }
+
+    /// Light the device as the scene describes
+    pub fn apply(&self, lpad: &mut LaunchpadMk2) {
+        lpad.draw_frame(&self.frame());
+        for led in &self.leds {
+            match led.effect {
+                Some(Effect::Flash) => lpad.set_flash(led.location.position(), led.color),
+                Some(Effect::Pulse) => lpad.set_pulse(led.location.position(), led.color),
+                None => {}
+            }
+        }
+        if let Some(ref scroll) = self.scroll {
+            let color = scroll.color.map_or(palette::WHITE, |c| c.nearest_palette());
+            lpad.scroll_text(color, scroll.looping, &scroll.text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_and_json() {
+        let toml = Scene::parse(r##"
+            all = "RED"
+            leds = [{ x = 3, y = 4, color = "#00ff00", effect = "pulse" }, { side = 2, color = 5 }]
+            [scroll]
+            text = "Hi"
+        "##, false).unwrap();
+        let json = Scene::parse(r##"{
+            "all": "RED",
+            "leds": [{"x": 3, "y": 4, "color": "#00ff00", "effect": "pulse"}, {"side": 2, "color": 5}],
+            "scroll": {"text": "Hi"}
+        }"##, true).unwrap();
+        for scene in &[toml, json] {
+            assert_eq!(scene.all, LedColor::Palette(palette::RED));
+            assert_eq!(scene.leds[0].location, Location::Pad { x: 3, y: 4 });
+            assert_eq!(scene.leds[0].effect, Some(Effect::Pulse));
+            assert_eq!(scene.leds[1].location, Location::Side(2));
+            assert_eq!(scene.leds[1].color, LedColor::Palette(5));
+            assert_eq!(scene.scroll.as_ref().map(|s| s.text.as_str()), Some("Hi"));
+        }
+
+        // Each format only as its own
+        assert!(Scene::parse(r#"{"all": "RED"}"#, false).is_err());
+        assert!(Scene::parse(r#"all = "RED""#, true).is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |text| Scene::parse(text, false).unwrap_err();
+        assert_eq!(error("rows = [{ row = 8, color = 5 }]"), "no row 8, rows are 0 to 7");
+        assert_eq!(error("columns = [{ column = 8, color = 5 }]"),
+                   "no column 8, columns are 0 to 7");
+        assert!(error("[scroll]\ntext = \"Grüße\"").contains("can not be scrolled"));
+        assert_eq!(error("leds = [{ x = 1, y = 2, color = 5, efect = \"flash\" }]"),
+                   "unknown field `efect` in an LED");
+        assert_eq!(error("leds = [{ top = 1, side = 2, color = 5 }]"),
+                   "unknown field `side` in an LED");
+        assert!(Scene::parse("rows = [{ row = 0, color = 5, colour = 6 }]", false).is_err());
+        assert!(Scene::parse("leds = [{ x = 8, y = 0, color = 5 }]", false).is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        let scene = Scene::parse(r#"
+            all = "BLUE"
+            rows = [{ row = 1, color = "RED" }]
+            columns = [{ column = 2, color = "LIME" }]
+            leds = [
+                { x = 2, y = 1, color = "WHITE" },
+                { x = 3, y = 1, color = "YELLOW", effect = "flash" },
+                { top = 0, color = "CYAN" },
+            ]
+        "#, false).unwrap();
+        let frame = scene.frame();
+        assert_eq!(frame.pad(0, 0), LedColor::Palette(palette::BLUE));
+        assert_eq!(frame.pad(0, 1), LedColor::Palette(palette::RED));
+        assert_eq!(frame.pad(2, 0), LedColor::Palette(palette::LIME));
+        assert_eq!(frame.pad(2, 1), LedColor::Palette(palette::WHITE));
+        // Effects are applied on top of the frame
+        assert_eq!(frame.pad(3, 1), LedColor::Palette(palette::RED));
+        assert_eq!(frame.top(0), LedColor::Palette(palette::CYAN));
+        assert_eq!(frame.top(1), LedColor::Palette(palette::BLUE));
+        assert_eq!(frame.side(7), LedColor::Palette(palette::BLUE));
+    }
+}
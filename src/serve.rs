@@ -0,0 +1,481 @@
+//! A local HTTP and WebSocket server driving the device with JSON.
+//!
+//! * `GET /frame` returns what the device shows, as
+//!   `{"pads": [[color; 8]; 8], "top": [color; 8], "side": [color; 8]}` with
+//!   the top row of pads first
+//! * `POST /frame` shows a frame in the same form. Parts left out are kept.
+//! * `POST /commands` runs a command, or an array of them
+//! * `GET /events` upgrades to a WebSocket streaming presses and releases,
+//!   and accepts commands too
+//!
+//! Request bodies are limited to 64 KiB.
+//!
+//! Colors, LEDs and events are written as the library's `serde` support
+//! writes them, like in scene files. A color is `"off"`, a palette index, a
+//! palette name or `"#rrggbb"`. An LED is `"x": 0...7, "y": 0...7` for a pad,
+//! counting from the top left, or `"top": 0...7` or `"side": 0...7` for a
+//! button. The commands are:
+//!
+//! ```text
+//! {"command": "led", "x": 0, "y": 0, "color": "RED"}
+//! {"command": "row", "y": 0, "color": "#ff8000"}
+//! {"command": "column", "x": 0, "color": 5}
+//! {"command": "all", "color": "#102030"}
+//! {"command": "clear"}
+//! {"command": "scroll", "text": "Hello", "color": "WHITE", "loop": false}
+//! ```
+//!
+//! and events look like `{"event": "press", "x": 3, "y": 4, "velocity": 90}`,
+//! `{"event": "release", "top": 2}` or `{"event": "scroll_finished"}`.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use launchpad::*;
+use serde::Deserialize;
+use serde_json::{self, Map, Value};
+use tungstenite::{self, Message};
+use tungstenite::error::ProtocolError;
+
+/// Largest request body accepted, in bytes
+const MAX_BODY: usize = 64 * 1024;
+
+/// Something a connection needs the device for
+pub enum Request {
+    /// Reply with the frame shown
+    State(Sender<Value>),
+    /// Show a frame, or run commands, replying whether that worked
+    Frame(Value, Sender<Result<(), String>>),
+    Commands(Value, Sender<Result<(), String>>),
+    /// Send every event from now on, as JSON
+    Subscribe(Sender<String>),
+}
+
+/// Accept connections in the background, passing their requests on to be
+/// handled with the device by `Server::handle`
+pub fn listen(address: &str) -> io::Result<Receiver<Request>> {
+    let listener = TcpListener::bind(address)?;
+    let (requests, received) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let requests = requests.clone();
+            thread::spawn(move || {
+                if let Err(e) = connection(stream, requests) {
+                    eprintln!("{}", e);
+                }
+            });
+        }
+    });
+    Ok(received)
+}
+
+/// The device's side of the server: the frame it shows and who listens for
+/// its events
+pub struct Server {
+    shown: Frame,
+    subscribers: Vec<Sender<String>>,
+}
+
+impl Server {
+    /// Clear the device and start serving it
+    pub fn new(lpad: &mut LaunchpadMk2) -> Server {
+        let shown = Frame::new();
+        lpad.draw_frame(&shown);
+        Server {
+            shown,
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Handle a request from a connection
+    pub fn handle(&mut self, lpad: &mut LaunchpadMk2, request: Request) {
+        // A reply can only fail if the connection went away meanwhile
+        match request {
+            Request::State(reply) => {
+                let _ = reply.send(serde_json::to_value(&self.shown).expect("Frames are always JSON"));
+            }
+            Request::Frame(value, reply) => {
+                let mut frame = self.shown.clone();
+                let result = read_frame(value, &mut frame);
+                if result.is_ok() {
+                    self.show(lpad, frame);
+                }
+                let _ = reply.send(result);
+            }
+            Request::Commands(value, reply) => {
+                let commands = match value {
+                    Value::Array(commands) => commands,
+                    command => vec![command],
+                };
+                let result = commands.into_iter().try_for_each(|command| {
+                    let command = Command::deserialize(command).map_err(|e| e.to_string())?;
+                    self.command(lpad, command)
+                });
+                let _ = reply.send(result);
+            }
+            Request::Subscribe(events) => self.subscribers.push(events),
+        }
+    }
+
+    /// Send an event to everyone subscribed
+    pub fn broadcast(&mut self, event: &Event) {
+        // Only events from positions without a button have no JSON
+        if let Ok(json) = serde_json::to_string(event) {
+            self.subscribers.retain(|s| s.send(json.clone()).is_ok());
+        }
+    }
+
+    fn show(&mut self, lpad: &mut LaunchpadMk2, frame: Frame) {
+        lpad.draw_frame_diff(&frame, &self.shown);
+        self.shown = frame;
+    }
+
+    fn command(&mut self, lpad: &mut LaunchpadMk2, command: Command) -> Result<(), String> {
+        let mut frame = self.shown.clone();
+        match command {
+            Command::Led { location, color } => frame.set(location, color),
+            Command::Row { y, color } => {
+                check_line("row", y)?;
+                (0..GRID_SIZE).for_each(|x| frame.set_pad(x, y, color));
+            }
+            Command::Column { x, color } => {
+                check_line("column", x)?;
+                (0..GRID_SIZE).for_each(|y| frame.set_pad(x, y, color));
+            }
+            Command::All { color } => {
+                frame.fill_pads(color);
+                for i in 0..GRID_SIZE {
+                    frame.set_top(i, color);
+                    frame.set_side(i, color);
+                }
+            }
+            Command::Clear => frame = Frame::new(),
+            Command::Scroll { text, color, looping } => {
+                let color = color.map_or(palette::WHITE, |c| c.nearest_palette());
+                lpad.scroll_text(color, looping, ScrollText::new().text(&text).as_str());
+                // Scrolling blanks the device
+                self.shown = Frame::new();
+                return Ok(());
+            }
+        }
+        self.show(lpad, frame);
+        Ok(())
+    }
+}
+
+/// Something to do with the device, see the module documentation
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    Led {
+        #[serde(flatten)]
+        location: Location,
+        color: LedColor,
+    },
+    Row { y: usize, color: LedColor },
+    Column { x: usize, color: LedColor },
+    All { color: LedColor },
+    Clear,
+    Scroll {
+        text: String,
+        color: Option<LedColor>,
+        #[serde(default, rename = "loop")]
+        looping: bool,
+    },
+}
+
+/// Make sure a row or column is one of the pads'
+fn check_line(kind: &str, index: usize) -> Result<(), String> {
+    if index < GRID_SIZE {
+        Ok(())
+    } else {
+        Err(format!("no {} {}, {}s are 0 to {}", kind, index, kind, GRID_SIZE - 1))
+    }
+}
+
+fn connection(stream: TcpStream, requests: Sender<Request>) -> Result<(), String> {
+    let head = peek_head(&stream).map_err(|e| e.to_string())?;
+    let (method, path, upgrade) = request_line(&head);
+
+    if method == "GET" && path == "/events" && upgrade {
+        return websocket(stream, requests);
+    }
+
+    let mut reader = BufReader::new(&stream);
+    let length = content_length(&mut reader)?;
+    if length > MAX_BODY {
+        let message = format!("the body is over {} bytes", MAX_BODY);
+        return respond(&stream, "413 Payload Too Large", Some(error_json(&message))).map_err(|e| e.to_string());
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+
+    let reply = |make: fn(Value, Sender<Result<(), String>>) -> Request| {
+        let value: Value = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+        let (tx, rx) = mpsc::channel();
+        requests.send(make(value, tx)).map_err(|e| e.to_string())?;
+        rx.recv().map_err(|e| e.to_string())?
+    };
+    let (status, body) = match (method.as_str(), path.as_str()) {
+        ("GET", "/frame") => {
+            let (tx, rx) = mpsc::channel();
+            requests.send(Request::State(tx)).map_err(|e| e.to_string())?;
+            ("200 OK", Some(rx.recv().map_err(|e| e.to_string())?))
+        }
+        ("POST", "/frame") => result_response(reply(Request::Frame)),
+        ("POST", "/commands") => result_response(reply(Request::Commands)),
+        _ => ("404 Not Found", Some(error_json("not found"))),
+    };
+    respond(&stream, status, body).map_err(|e| e.to_string())
+}
+
+/// The method and path of a request, and whether it asks for a WebSocket
+fn request_line(head: &str) -> (String, String, bool) {
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_string();
+    let path = request_line.next().unwrap_or("").to_string();
+    let upgrade = head.lines()
+        .any(|l| l.to_ascii_lowercase().starts_with("upgrade:") && l.to_ascii_lowercase().contains("websocket"));
+    (method, path, upgrade)
+}
+
+/// Read the request line and headers, returning the length of the body
+fn content_length<R: BufRead>(reader: &mut R) -> Result<usize, String> {
+    let mut length = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("request headers cut short".to_string());
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            return Ok(length);
+        }
+        let mut parts = header.splitn(2, ':');
+        if parts.next().unwrap_or("").eq_ignore_ascii_case("content-length") {
+            length = parts.next().unwrap_or("").trim().parse().map_err(|_| "bad content length")?;
+        }
+    }
+}
+
+fn result_response(result: Result<(), String>) -> (&'static str, Option<Value>) {
+    match result {
+        Ok(()) => ("204 No Content", None),
+        Err(e) => ("400 Bad Request", Some(error_json(&e))),
+    }
+}
+
+/// The request line and headers, left unread so a WebSocket handshake can
+/// still read them
+fn peek_head(stream: &TcpStream) -> io::Result<String> {
+    let mut buf = [0; 8192];
+    loop {
+        let len = stream.peek(&mut buf)?;
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(end) = buf[..len].windows(4).position(|w| w == b"\r\n\r\n") {
+            return Ok(String::from_utf8_lossy(&buf[..end]).into_owned());
+        }
+        if len == buf.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request headers too long"));
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn respond(mut stream: &TcpStream, status: &str, body: Option<Value>) -> io::Result<()> {
+    let body = body.map(|b| b.to_string()).unwrap_or_default();
+    write!(stream,
+           "HTTP/1.1 {}\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\
+            \r\n\
+            {}",
+           status,
+           body.len(),
+           body)
+}
+
+fn websocket(stream: TcpStream, requests: Sender<Request>) -> Result<(), String> {
+    // Wake up regularly to pass on events while waiting for commands
+    stream.set_read_timeout(Some(Duration::from_millis(10))).map_err(|e| e.to_string())?;
+    let mut socket = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+    let (tx, events) = mpsc::channel();
+    requests.send(Request::Subscribe(tx)).map_err(|e| e.to_string())?;
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let result = serde_json::from_str(&text).map_err(|e| e.to_string()).and_then(|value| {
+                    let (tx, rx) = mpsc::channel();
+                    requests.send(Request::Commands(value, tx)).map_err(|e| e.to_string())?;
+                    rx.recv().map_err(|e| e.to_string())?
+                });
+                if let Err(e) = result {
+                    socket.send(Message::Text(error_json(&e).to_string())).map_err(|e| e.to_string())?;
+                }
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(ref e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {}
+            Err(tungstenite::Error::ConnectionClosed) |
+            Err(tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        }
+        while let Ok(event) = events.try_recv() {
+            socket.send(Message::Text(event)).map_err(|e| e.to_string())?;
+        }
+    }
+}
+
+fn error_json(message: &str) -> Value {
+    let mut map = Map::new();
+    map.insert("error".to_string(), Value::from(message));
+    Value::Object(map)
+}
+
+/// A frame as posted, where parts left out are kept
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrameUpdate {
+    pads: Option<[[LedColor; GRID_SIZE]; GRID_SIZE]>,
+    top: Option<[LedColor; GRID_SIZE]>,
+    side: Option<[LedColor; GRID_SIZE]>,
+}
+
+fn read_frame(value: Value, frame: &mut Frame) -> Result<(), String> {
+    let update = FrameUpdate::deserialize(value).map_err(|e| e.to_string())?;
+    for (y, row) in update.pads.iter().flatten().enumerate() {
+        for (x, &color) in row.iter().enumerate() {
+            frame.set_pad(x, y, color);
+        }
+    }
+    for (i, &color) in update.top.iter().flatten().enumerate() {
+        frame.set_top(i, color);
+    }
+    for (i, &color) in update.side.iter().flatten().enumerate() {
+        frame.set_side(i, color);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Shutdown;
+
+    use super::*;
+
+    #[test]
+    fn request_head() {
+        let head = "GET /events HTTP/1.1\r\nHost: localhost\r\nUpgrade: WebSocket";
+        assert_eq!(request_line(head), ("GET".to_string(), "/events".to_string(), true));
+        assert_eq!(request_line("POST /frame HTTP/1.1"), ("POST".to_string(), "/frame".to_string(), false));
+
+        let mut head = &b"POST /frame HTTP/1.1\r\ncontent-LENGTH: 12\r\n\r\n{}"[..];
+        assert_eq!(content_length(&mut head), Ok(12));
+        assert_eq!(head, b"{}");
+        assert_eq!(content_length(&mut &b"GET / HTTP/1.1\r\n\r\n"[..]), Ok(0));
+        assert!(content_length(&mut &b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"[..]).is_err());
+        assert!(content_length(&mut &b"POST / HTTP/1.1\r\nContent-Length: 2\r\n"[..]).is_err());
+    }
+
+    /// Run requests through a server on an emulated device
+    fn run(requests: Vec<Request>) -> Emulator {
+        let (emulator, mut lpad) = Emulator::new();
+        let mut server = Server::new(&mut lpad);
+        for request in requests {
+            server.handle(&mut lpad, request);
+        }
+        emulator
+    }
+
+    fn commands(json: Value) -> Result<(), String> {
+        let (tx, rx) = mpsc::channel();
+        run(vec![Request::Commands(json, tx)]);
+        rx.recv().unwrap()
+    }
+
+    #[test]
+    fn commands_light_the_device() {
+        let (tx, rx) = mpsc::channel();
+        let (state, frame) = mpsc::channel();
+        let command = serde_json::json!([{"command": "row", "y": 0, "color": "#ff0000"},
+                                         {"command": "led", "side": 2, "color": "BLUE"},
+                                         {"command": "led", "x": 1, "y": 0, "color": "#000001"}]);
+        let emulator = run(vec![Request::Commands(command, tx), Request::State(state)]);
+        assert_eq!(rx.recv().unwrap(), Ok(()));
+
+        let frame = frame.recv().unwrap();
+        assert_eq!(frame["pads"][0], serde_json::json!(["#ff0000", "#000001", "#ff0000", "#ff0000", "#ff0000", "#ff0000",
+                                                       "#ff0000", "#ff0000"]));
+        assert_eq!(frame["side"][2], serde_json::json!(palette::BLUE));
+        let screen = emulator.screen();
+        assert_eq!(screen.led(side_position(2)).color, LedColor::Palette(palette::BLUE));
+        assert!(matches!(screen.led(pad_position(1, 0)).color, LedColor::Rgb(_)));
+
+        assert!(commands(serde_json::json!({"command": "led", "x": 8, "y": 0, "color": 5})).is_err());
+        assert!(commands(serde_json::json!({"command": "all", "color": 128})).is_err());
+        assert!(commands(serde_json::json!({"command": "column", "x": 0, "color": "MAUVE"})).is_err());
+        assert!(commands(serde_json::json!({"command": "row", "y": 8, "color": 5})).is_err());
+        assert!(commands(serde_json::json!({"command": "all", "color": [255, 0, 0]})).is_err());
+        assert!(commands(serde_json::json!({"command": "spin"})).is_err());
+    }
+
+    #[test]
+    fn frames() {
+        let mut frame = Frame::new();
+        let json = serde_json::json!({"top": ["off", 1, 2, 3, 4, 5, 6, "#ff0000"]});
+        assert_eq!(read_frame(json.clone(), &mut frame), Ok(()));
+        assert_eq!(frame.top(7), LedColor::Rgb(RGBColor::new(255, 0, 0)));
+        // The same form as the library's, which scene files use too
+        let written = serde_json::to_value(&frame).unwrap();
+        assert_eq!(written["top"], json["top"]);
+        assert_eq!(serde_json::from_value::<Frame>(written).unwrap(), frame);
+
+        assert!(read_frame(serde_json::json!({"side": [1, 2]}), &mut frame).is_err());
+        assert!(read_frame(serde_json::json!({"pads": [[1]]}), &mut frame).is_err());
+        assert!(read_frame(serde_json::json!({"tops": []}), &mut frame).is_err());
+        assert!(read_frame(serde_json::json!([]), &mut frame).is_err());
+    }
+
+    /// Send a request over HTTP and return the response
+    fn http(request: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let (requests, received) = mpsc::channel();
+        let server = thread::spawn(move || connection(stream, requests));
+        client.write_all(request).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let (_emulator, mut lpad) = Emulator::new();
+        let mut handler = Server::new(&mut lpad);
+        if let Ok(request) = received.recv() {
+            handler.handle(&mut lpad, request);
+        }
+        server.join().unwrap().unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn http_requests() {
+        let response = http(b"POST /commands HTTP/1.1\r\nContent-Length: 19\r\n\r\n{\"command\":\"clear\"}");
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", response);
+        assert!(!response.to_ascii_lowercase().contains("access-control"));
+
+        let response = http(b"POST /frame HTTP/1.1\r\nContent-Length: 999999999999\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{}", response);
+
+        let response = http(b"GET /nowhere HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", response);
+    }
+}
@@ -0,0 +1,201 @@
+//! Showing a device in the terminal: an emulated one, for working on pad
+//! applications without hardware, or a mirror of what a real one has been
+//! sent.
+//!
+//! Buttons of an emulated device are pressed with the mouse, or by moving a
+//! cursor with the arrow keys and pressing Space to tap or Enter to hold and
+//! let go. `q`, Escape or Ctrl-C quit.
+
+use std::io::{self, Write};
+use std::panic;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEventKind,
+                       KeyModifiers, MouseButton, MouseEventKind};
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use launchpad::*;
+
+const EMULATOR_HELP: &str = "Click, or move with the arrow keys and press Space to tap or Enter to \
+                             hold. q quits.";
+const MIRROR_HELP: &str = "q quits.";
+
+/// Velocity of emulated pad presses
+const VELOCITY: u8 = 100;
+
+/// Whether the terminal is taken over
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Whether quitting is left to the program, see `defer_quit`
+static DEFER_QUIT: AtomicBool = AtomicBool::new(false);
+/// Whether the user quit, when quitting is left to the program
+static QUIT: AtomicBool = AtomicBool::new(false);
+
+/// What is shown
+enum View {
+    Emulator(Emulator),
+    Mirror(Mirror),
+}
+
+/// Take over the terminal to show an emulated device, and return the device
+/// to drive. The program exits when the user quits the emulator.
+pub fn emulate() -> io::Result<LaunchpadMk2> {
+    let (emulator, lpad) = Emulator::new();
+    take_over(View::Emulator(emulator))?;
+    Ok(lpad)
+}
+
+/// Take over the terminal to show what is sent to a device. The program
+/// exits when the user quits the mirror.
+pub fn mirror(lpad: &mut LaunchpadMk2) -> io::Result<()> {
+    take_over(View::Mirror(Mirror::attach(lpad)))
+}
+
+fn take_over(view: View) -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    ACTIVE.store(true, Ordering::SeqCst);
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture, cursor::Hide)?;
+
+    // Give the terminal back before reporting a panic
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        report(info);
+    }));
+
+    thread::spawn(move || {
+        if let Err(e) = run(&view) {
+            restore();
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    });
+    Ok(())
+}
+
+/// Give the terminal back, if it was taken over
+pub fn restore() {
+    if ACTIVE.swap(false, Ordering::SeqCst) {
+        let _ = execute!(io::stdout(), DisableMouseCapture, LeaveAlternateScreen, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Leave quitting to the program, so it can tidy up first: when the user
+/// quits, `quitting` tells so instead of the program exiting
+pub fn defer_quit() {
+    DEFER_QUIT.store(true, Ordering::SeqCst);
+}
+
+/// Whether the user quit, after `defer_quit`
+pub fn quitting() -> bool {
+    QUIT.load(Ordering::SeqCst)
+}
+
+/// Keep showing the device until the user quits
+pub fn wait() -> ! {
+    loop {
+        thread::park();
+    }
+}
+
+fn run(view: &View) -> io::Result<()> {
+    // Cursor position in the grid of LEDs, top buttons being row 0
+    let (mut column, mut row) = (0, 1);
+    let mut held: Option<Location> = None;
+    let mut clicked: Option<Location> = None;
+
+    loop {
+        // Redraw at about 30 frames per second, or on input
+        while event::poll(Duration::from_millis(33))? {
+            let input = event::read()?;
+            if let event::Event::Key(key) = input {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.code == KeyCode::Char('q') || key.code == KeyCode::Esc || ctrl_c {
+                    if !DEFER_QUIT.load(Ordering::SeqCst) {
+                        quit();
+                    }
+                    QUIT.store(true, Ordering::SeqCst);
+                }
+            }
+            let emulator = match *view {
+                View::Emulator(ref emulator) => emulator,
+                View::Mirror(_) => continue,
+            };
+
+            match input {
+                event::Event::Key(key) if key.kind != KeyEventKind::Release => {
+                    let at = location_at(column * 3, row);
+                    match key.code {
+                        KeyCode::Left => column = column.saturating_sub(1),
+                        KeyCode::Right => column = (column + 1).min(GRID_SIZE),
+                        KeyCode::Up => row = row.saturating_sub(1),
+                        KeyCode::Down => row = (row + 1).min(GRID_SIZE),
+                        KeyCode::Char(' ') => {
+                            if let Some(location) = at {
+                                emulator.press(location, VELOCITY);
+                                emulator.release(location);
+                            }
+                        }
+                        KeyCode::Enter => {
+                            match held.take() {
+                                Some(location) => emulator.release(location),
+                                None => {
+                                    held = at;
+                                    if let Some(location) = at {
+                                        emulator.press(location, VELOCITY);
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                event::Event::Mouse(mouse) => {
+                    let at = location_at(mouse.column as usize, mouse.row as usize);
+                    match mouse.kind {
+                        MouseEventKind::Down(MouseButton::Left) => {
+                            clicked = at;
+                            if let Some(location) = at {
+                                emulator.press(location, VELOCITY);
+                            }
+                        }
+                        MouseEventKind::Drag(MouseButton::Left) if at != clicked => {
+                            if let Some(location) = clicked {
+                                emulator.release(location);
+                            }
+                            clicked = at;
+                            if let Some(location) = at {
+                                emulator.press(location, VELOCITY);
+                            }
+                        }
+                        MouseEventKind::Up(MouseButton::Left) => {
+                            if let Some(location) = clicked.take() {
+                                emulator.release(location);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut out = io::stdout();
+        queue!(out, cursor::MoveTo(0, 0))?;
+        let (screen, help) = match *view {
+            View::Emulator(ref emulator) => (emulator.render(location_at(column * 3, row)), EMULATOR_HELP),
+            View::Mirror(ref mirror) => (mirror.render(), MIRROR_HELP),
+        };
+        write!(out, "{}\r\n{}\r\n", screen, help)?;
+        queue!(out, terminal::Clear(ClearType::FromCursorDown))?;
+        out.flush()?;
+    }
+}
+
+fn quit() -> ! {
+    restore();
+    process::exit(0);
+}